@@ -0,0 +1,108 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+use serde::{Deserialize, Serialize};
+
+/// How quality scores are encoded as characters.
+///
+/// Records are normalized to Phred+33 when they are read, so statistics only
+/// ever see Phred+33 qualities.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum QualityEncoding {
+    /// Sanger and Illumina 1.8+, Phred scores from `!` (0).
+    Phred33,
+    /// Illumina 1.3 to 1.7, Phred scores from `@` (0).
+    Phred64,
+    /// Solexa and Illumina before 1.3, odds based scores from `;` (-5).
+    Solexa,
+}
+
+// Highest character of Phred+33 data from Illumina 1.8+ (Q42). Data whose
+// characters all lie between `@` and this is taken as Phred+33, as it is
+// more likely high quality Phred+33 than low quality Phred+64.
+const MAX_ILLUMINA_33: u8 = b'K';
+
+// Phred+33 character for every Solexa character from `;`.
+static SOLEXA_TO_PHRED33: LazyLock<Vec<u8>> = LazyLock::new(|| {
+    (b';'..=b'~')
+        .map(|c| {
+            let solexa = (c as i32 - 64) as f64;
+            let phred = 10.0 * (10f64.powf(solexa / 10.0) + 1.0).log10();
+            phred.round() as u8 + 33
+        })
+        .collect()
+});
+
+impl QualityEncoding {
+    /// Lowest valid quality character.
+    pub fn min_char(self) -> u8 {
+        match self {
+            QualityEncoding::Phred33 => b'!',
+            QualityEncoding::Phred64 => b'@',
+            QualityEncoding::Solexa => b';',
+        }
+    }
+
+    /// The encoding of quality characters ranging from `min` to `max`.
+    pub fn detect(min: u8, max: u8) -> Self {
+        if min < b';' || max <= MAX_ILLUMINA_33 {
+            QualityEncoding::Phred33
+        } else if min < b'@' {
+            QualityEncoding::Solexa
+        } else {
+            QualityEncoding::Phred64
+        }
+    }
+
+    /// The encoding of the quality lines `quals`, Phred+33 if they are empty.
+    pub fn detect_from<'a>(quals: impl IntoIterator<Item = &'a [u8]>) -> Self {
+        let (min, max) = quals
+            .into_iter()
+            .flatten()
+            .fold((u8::MAX, u8::MIN), |(min, max), &q| {
+                (min.min(q), max.max(q))
+            });
+        if min > max {
+            QualityEncoding::Phred33
+        } else {
+            QualityEncoding::detect(min, max)
+        }
+    }
+
+    /// Convert the quality characters `qual`, valid for this encoding, to Phred+33.
+    pub fn normalize(self, qual: &mut [u8]) {
+        match self {
+            QualityEncoding::Phred33 => {}
+            QualityEncoding::Phred64 => qual.iter_mut().for_each(|q| *q -= 31),
+            QualityEncoding::Solexa => qual
+                .iter_mut()
+                .for_each(|q| *q = SOLEXA_TO_PHRED33[(*q - b';') as usize]),
+        }
+    }
+}
+
+impl fmt::Display for QualityEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            QualityEncoding::Phred33 => "Phred+33",
+            QualityEncoding::Phred64 => "Phred+64",
+            QualityEncoding::Solexa => "Solexa+64",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Parses `33`, `64` or `solexa`, as given on the command line.
+impl FromStr for QualityEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "33" => Ok(QualityEncoding::Phred33),
+            "64" => Ok(QualityEncoding::Phred64),
+            "solexa" => Ok(QualityEncoding::Solexa),
+            _ => Err(format!("expected 33, 64 or solexa, found '{}'", s)),
+        }
+    }
+}
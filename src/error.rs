@@ -0,0 +1,241 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::encoding::QualityEncoding;
+
+/// Describes what is wrong with a FASTQ record.
+#[derive(Debug)]
+pub enum FastqErrorKind {
+    /// The header line does not start with `@`.
+    MissingHeader { found: u8 },
+    /// The third line of the record does not start with `+`.
+    MissingSeparator { found: u8 },
+    /// The `+` line repeats a name that differs from the header.
+    NameMismatch { header: String, separator: String },
+    /// The sequence and quality lines have different lengths.
+    LengthMismatch { seq: usize, qual: usize },
+    /// The sequence contains a character that is not a nucleotide code.
+    InvalidBase { base: u8, column: usize },
+    /// The quality line contains a character outside of `!`..=`~`.
+    InvalidQuality { qual: u8, column: usize },
+    /// The quality line contains a character below the range of the encoding.
+    QualityOutOfRange {
+        qual: u8,
+        column: usize,
+        encoding: QualityEncoding,
+    },
+    /// The input ended in the middle of a record.
+    Truncated,
+    /// Reading from the underlying input failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for FastqErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FastqErrorKind::MissingHeader { found } => {
+                write!(
+                    f,
+                    "expected '@' at start of header, found {:?}",
+                    *found as char
+                )
+            }
+            FastqErrorKind::MissingSeparator { found } => {
+                write!(f, "expected '+' separator line, found {:?}", *found as char)
+            }
+            FastqErrorKind::NameMismatch { header, separator } => write!(
+                f,
+                "separator name '{}' does not match header '{}'",
+                separator, header
+            ),
+            FastqErrorKind::LengthMismatch { seq, qual } => write!(
+                f,
+                "sequence length ({}) differs from quality length ({})",
+                seq, qual
+            ),
+            FastqErrorKind::InvalidBase { base, column } => {
+                write!(f, "invalid base {:?} in column {}", *base as char, column)
+            }
+            FastqErrorKind::InvalidQuality { qual, column } => write!(
+                f,
+                "invalid quality character {:?} in column {}",
+                *qual as char, column
+            ),
+            FastqErrorKind::QualityOutOfRange {
+                qual,
+                column,
+                encoding,
+            } => write!(
+                f,
+                "quality character {:?} in column {} is out of range for {}",
+                *qual as char, column, encoding
+            ),
+            FastqErrorKind::Truncated => write!(f, "input ends in the middle of a record"),
+            FastqErrorKind::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+/// An error raised while parsing a FASTQ file, with the position it occurred at.
+#[derive(Debug)]
+pub struct FastqError {
+    pub kind: FastqErrorKind,
+    /// 0-based index of the record being parsed.
+    pub record: u64,
+    /// Byte offset of the start of the offending line.
+    pub offset: u64,
+    /// 1-based number of the offending line.
+    pub line: u64,
+}
+
+impl fmt::Display for FastqError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "record {} (line {}, byte {}): {}",
+            self.record + 1,
+            self.line,
+            self.offset,
+            self.kind
+        )
+    }
+}
+
+impl std::error::Error for FastqError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            FastqErrorKind::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Describes why two records are not mates.
+#[derive(Debug)]
+pub enum PairErrorKind {
+    /// The read names differ after removing `/1` and `/2`.
+    NameMismatch { r1: String, r2: String },
+    /// The file of this mate ended while the other file has more records.
+    MissingMate { mate: u8 },
+}
+
+/// An error in the pairing of R1 and R2 records.
+#[derive(Debug)]
+pub struct PairError {
+    pub kind: PairErrorKind,
+    /// 0-based index of the pair.
+    pub pair: u64,
+}
+
+impl fmt::Display for PairError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            PairErrorKind::NameMismatch { r1, r2 } => write!(
+                f,
+                "pair {}: read names '{}' and '{}' do not match",
+                self.pair + 1,
+                r1,
+                r2
+            ),
+            PairErrorKind::MissingMate { mate } => write!(
+                f,
+                "R{} ends after {} records, the other file has more",
+                mate, self.pair
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PairError {}
+
+/// Errors that end a scan of a FASTQ input.
+#[derive(Debug)]
+pub enum FastqScanError {
+    /// The input could not be opened.
+    Open { path: PathBuf, source: io::Error },
+    /// Reading from the input failed.
+    Io(FastqError),
+    /// A record is malformed.
+    Format(FastqError),
+    /// The input ends in the middle of a record or of a compressed stream.
+    Truncated(FastqError),
+    /// The compressed stream is corrupt, e.g. a CRC mismatch.
+    Decompression(FastqError),
+    /// An error in one of the files of a pair, `mate` is 1 or 2.
+    Mate {
+        mate: u8,
+        source: Box<FastqScanError>,
+    },
+    /// The records of R1 and R2 do not belong together.
+    Pairing(PairError),
+    /// An output file could not be created or written.
+    Write { path: PathBuf, source: io::Error },
+}
+
+impl FastqScanError {
+    /// Process exit code for this kind of error.
+    ///
+    /// Codes 1 and 2 are left for general and command line errors.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            FastqScanError::Open { .. } | FastqScanError::Io(_) | FastqScanError::Write { .. } => 3,
+            FastqScanError::Format(_) => 4,
+            FastqScanError::Truncated(_) => 5,
+            FastqScanError::Decompression(_) => 6,
+            FastqScanError::Mate { source, .. } => source.exit_code(),
+            FastqScanError::Pairing(_) => 7,
+        }
+    }
+}
+
+impl From<FastqError> for FastqScanError {
+    fn from(error: FastqError) -> Self {
+        match &error.kind {
+            FastqErrorKind::Truncated => FastqScanError::Truncated(error),
+            FastqErrorKind::Io(e) => match e.kind() {
+                io::ErrorKind::UnexpectedEof => FastqScanError::Truncated(error),
+                io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput => {
+                    FastqScanError::Decompression(error)
+                }
+                _ => FastqScanError::Io(error),
+            },
+            _ => FastqScanError::Format(error),
+        }
+    }
+}
+
+impl fmt::Display for FastqScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FastqScanError::Open { path, source } => {
+                write!(f, "cannot open {:?}: {}", path, source)
+            }
+            FastqScanError::Io(e) => write!(f, "read error at {}", e),
+            FastqScanError::Format(e) => write!(f, "invalid FASTQ at {}", e),
+            FastqScanError::Truncated(e) => write!(f, "truncated input at {}", e),
+            FastqScanError::Decompression(e) => write!(f, "corrupt compressed input at {}", e),
+            FastqScanError::Mate { mate, source } => write!(f, "R{}: {}", mate, source),
+            FastqScanError::Pairing(e) => write!(f, "unpaired reads at {}", e),
+            FastqScanError::Write { path, source } => {
+                write!(f, "cannot write {:?}: {}", path, source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FastqScanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FastqScanError::Open { source, .. } | FastqScanError::Write { source, .. } => {
+                Some(source)
+            }
+            FastqScanError::Io(e)
+            | FastqScanError::Format(e)
+            | FastqScanError::Truncated(e)
+            | FastqScanError::Decompression(e) => Some(e),
+            FastqScanError::Mate { source, .. } => Some(source.as_ref()),
+            FastqScanError::Pairing(e) => Some(e),
+        }
+    }
+}
@@ -0,0 +1,370 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::runner::FastqRecord;
+
+/// The read header layouts that can be recognised.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum HeaderFormat {
+    /// `@instrument:run:flowcell:lane:tile:x:y read:filtered:control:index`
+    Casava18,
+    /// Illumina before Casava 1.8, `@instrument:lane:tile:x:y#index/read`
+    IlluminaLegacy,
+    /// MGI/BGI, `@flowcellL1C001R0010000001/1`
+    Mgi,
+    /// PacBio, `@movie/zmw/ccs` or `@movie/zmw/start_end`
+    PacBio,
+    /// Oxford Nanopore, a UUID followed by `key=value` pairs
+    Ont,
+    /// Dumped from the SRA, `@SRR123.1`, optionally followed by the original name
+    Sra,
+}
+
+impl HeaderFormat {
+    /// The parser for headers of this format.
+    pub fn parser(self) -> &'static dyn HeaderParser {
+        match self {
+            HeaderFormat::Casava18 => &Casava18,
+            HeaderFormat::IlluminaLegacy => &IlluminaLegacy,
+            HeaderFormat::Mgi => &Mgi,
+            HeaderFormat::PacBio => &PacBio,
+            HeaderFormat::Ont => &Ont,
+            HeaderFormat::Sra => &Sra,
+        }
+    }
+}
+
+impl fmt::Display for HeaderFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            HeaderFormat::Casava18 => "Casava 1.8+",
+            HeaderFormat::IlluminaLegacy => "Illumina (pre Casava 1.8)",
+            HeaderFormat::Mgi => "MGI/BGI",
+            HeaderFormat::PacBio => "PacBio",
+            HeaderFormat::Ont => "Oxford Nanopore",
+            HeaderFormat::Sra => "SRA",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The information in a read header, independent of its format.
+///
+/// Fields a format does not have are `None`. Format specific values that have
+/// no field of their own, e.g. the `key=value` pairs of Nanopore reads, are
+/// kept in `attributes`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadHeader {
+    pub format: HeaderFormat,
+    pub instrument: Option<String>,
+    /// Run number or id, the SRA accession or the PacBio movie.
+    pub run: Option<String>,
+    pub flowcell_id: Option<String>,
+    pub lane: Option<usize>,
+    /// The tile, for MGI the field of view as `column * 1000 + row`.
+    pub tile: Option<usize>,
+    pub x: Option<usize>,
+    pub y: Option<usize>,
+    /// Member of a pair, 1 or 2.
+    pub read: Option<usize>,
+    /// `true` if the read did not pass the filter.
+    pub is_filtered: Option<bool>,
+    pub control_number: Option<usize>,
+    pub index: Option<String>,
+    /// SRA spot number or PacBio ZMW.
+    pub spot: Option<u64>,
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl ReadHeader {
+    fn new(format: HeaderFormat) -> Self {
+        ReadHeader {
+            format,
+            instrument: None,
+            run: None,
+            flowcell_id: None,
+            lane: None,
+            tile: None,
+            x: None,
+            y: None,
+            read: None,
+            is_filtered: None,
+            control_number: None,
+            index: None,
+            spot: None,
+            attributes: BTreeMap::new(),
+        }
+    }
+
+    /// Parse the header of `record`, trying all formats in turn.
+    pub fn parse(record: &FastqRecord) -> Option<Self> {
+        let (name, comment) = (record.name_str(), record.comment_str());
+        PARSERS
+            .iter()
+            .find_map(|parser| parser.parse(&name, &comment))
+    }
+
+    /// Parse the header of `record` as `format`.
+    pub fn parse_as(record: &FastqRecord, format: HeaderFormat) -> Option<Self> {
+        format
+            .parser()
+            .parse(&record.name_str(), &record.comment_str())
+    }
+
+    /// Parse the header of `record` as `format`, which is detected from the
+    /// first record with a known format, and try all formats only if that
+    /// fails. The reads of a file usually share one format.
+    pub fn parse_detected(record: &FastqRecord, format: &mut Option<HeaderFormat>) -> Option<Self> {
+        if format.is_none() {
+            *format = detect(record);
+        }
+        ReadHeader::parse_as(record, (*format)?).or_else(|| ReadHeader::parse(record))
+    }
+}
+
+/// Parses one header format.
+pub trait HeaderParser: Sync {
+    fn format(&self) -> HeaderFormat;
+
+    /// Parse the read name (up to the first whitespace, without `@`) and the
+    /// comment, `None` if the header is not in this format.
+    fn parse(&self, name: &str, comment: &str) -> Option<ReadHeader>;
+}
+
+/// All parsers, in the order they are tried. More specific formats come first.
+pub static PARSERS: [&dyn HeaderParser; 6] =
+    [&Casava18, &IlluminaLegacy, &Mgi, &PacBio, &Ont, &Sra];
+
+/// The format of the first parser that accepts the header of `record`.
+pub fn detect(record: &FastqRecord) -> Option<HeaderFormat> {
+    ReadHeader::parse(record).map(|header| header.format)
+}
+
+fn number<T: std::str::FromStr>(field: &str) -> Option<T> {
+    field.parse().ok()
+}
+
+pub struct Casava18;
+
+impl HeaderParser for Casava18 {
+    fn format(&self) -> HeaderFormat {
+        HeaderFormat::Casava18
+    }
+
+    fn parse(&self, name: &str, comment: &str) -> Option<ReadHeader> {
+        let fields: Vec<&str> = name.split(':').collect();
+        let [instrument, run, flowcell, lane, tile, x, y] = fields[..] else {
+            return None;
+        };
+        let mut header = ReadHeader::new(self.format());
+        header.instrument = Some(instrument.to_string());
+        header.run = Some(run.to_string());
+        header.flowcell_id = Some(flowcell.to_string());
+        header.lane = Some(number(lane)?);
+        header.tile = Some(number(tile)?);
+        header.x = Some(number(x)?);
+        header.y = Some(number(y)?);
+
+        // The comment is optional, e.g. after trimming tools dropped it
+        let info = comment.split_whitespace().next().unwrap_or("");
+        let fields: Vec<&str> = info.split(':').collect();
+        if let [read, filtered, control, index] = fields[..] {
+            header.read = Some(number(read)?);
+            header.is_filtered = match filtered {
+                "Y" => Some(true),
+                "N" => Some(false),
+                _ => return None,
+            };
+            header.control_number = Some(number(control)?);
+            header.index = Some(index.to_string());
+        }
+        Some(header)
+    }
+}
+
+pub struct IlluminaLegacy;
+
+impl HeaderParser for IlluminaLegacy {
+    fn format(&self) -> HeaderFormat {
+        HeaderFormat::IlluminaLegacy
+    }
+
+    fn parse(&self, name: &str, _comment: &str) -> Option<ReadHeader> {
+        let (name, read) = match name.rsplit_once('/') {
+            Some((name, read)) => (name, Some(number(read)?)),
+            None => (name, None),
+        };
+        let (name, index) = match name.rsplit_once('#') {
+            Some((name, index)) => (name, Some(index.to_string())),
+            None => (name, None),
+        };
+        let fields: Vec<&str> = name.split(':').collect();
+        let [instrument, lane, tile, x, y] = fields[..] else {
+            return None;
+        };
+        let mut header = ReadHeader::new(self.format());
+        header.instrument = Some(instrument.to_string());
+        header.lane = Some(number(lane)?);
+        header.tile = Some(number(tile)?);
+        header.x = Some(number(x)?);
+        header.y = Some(number(y)?);
+        header.read = read;
+        header.index = index;
+        Some(header)
+    }
+}
+
+pub struct Mgi;
+
+impl HeaderParser for Mgi {
+    fn format(&self) -> HeaderFormat {
+        HeaderFormat::Mgi
+    }
+
+    fn parse(&self, name: &str, _comment: &str) -> Option<ReadHeader> {
+        let (name, read) = match name.rsplit_once('/') {
+            Some((name, read)) => (name, Some(number(read)?)),
+            None => (name, None),
+        };
+        // flowcell, `L` lane, `C` column (3 digits), `R` row (3 digits), read number
+        let lane_at = name.rfind('L')?;
+        let (flowcell, rest) = name.split_at(lane_at);
+        let rest = rest.as_bytes();
+        if flowcell.is_empty()
+            || !rest.is_ascii()
+            || rest.len() < 11
+            || rest[2] != b'C'
+            || rest[6] != b'R'
+            || !rest[1].is_ascii_digit()
+        {
+            return None;
+        }
+        let rest = std::str::from_utf8(rest).ok()?;
+        let column: usize = number(&rest[3..6])?;
+        let row: usize = number(&rest[7..10])?;
+
+        let mut header = ReadHeader::new(self.format());
+        header.flowcell_id = Some(flowcell.to_string());
+        header.lane = Some(number(&rest[1..2])?);
+        header.tile = Some(column * 1000 + row);
+        header.spot = Some(number(&rest[10..])?);
+        header.read = read;
+        Some(header)
+    }
+}
+
+pub struct PacBio;
+
+impl HeaderParser for PacBio {
+    fn format(&self) -> HeaderFormat {
+        HeaderFormat::PacBio
+    }
+
+    fn parse(&self, name: &str, _comment: &str) -> Option<ReadHeader> {
+        let mut fields = name.split('/');
+        let movie = fields.next()?;
+        let zmw = number(fields.next()?)?;
+        let kind = fields.next()?;
+        if fields.next().is_some() {
+            return None;
+        }
+        // Movie names are `m<instrument>_<date>_<time>`
+        let (instrument, _) = movie.split_once('_')?;
+        if !instrument.starts_with('m') || instrument.len() < 2 {
+            return None;
+        }
+
+        let mut header = ReadHeader::new(self.format());
+        header.instrument = Some(instrument[1..].to_string());
+        header.run = Some(movie.to_string());
+        header.spot = Some(zmw);
+        if kind.split_once('_').is_some_and(|(start, end)| {
+            number::<u64>(start).is_some() && number::<u64>(end).is_some()
+        }) {
+            header
+                .attributes
+                .insert("subread".to_string(), kind.to_string());
+        } else if kind.chars().all(|c| c.is_ascii_alphanumeric()) {
+            header
+                .attributes
+                .insert("type".to_string(), kind.to_string());
+        } else {
+            return None;
+        }
+        Some(header)
+    }
+}
+
+pub struct Ont;
+
+impl HeaderParser for Ont {
+    fn format(&self) -> HeaderFormat {
+        HeaderFormat::Ont
+    }
+
+    fn parse(&self, name: &str, comment: &str) -> Option<ReadHeader> {
+        // Read ids are UUIDs
+        let groups: Vec<usize> = name.split('-').map(str::len).collect();
+        if groups != [8, 4, 4, 4, 12] || !name.chars().all(|c| c == '-' || c.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        let mut header = ReadHeader::new(self.format());
+        for pair in comment.split_whitespace() {
+            if let Some((key, value)) = pair.split_once('=') {
+                header.attributes.insert(key.to_string(), value.to_string());
+            }
+        }
+        header.run = header.attributes.get("runid").cloned();
+        header.flowcell_id = header.attributes.get("flow_cell_id").cloned();
+        Some(header)
+    }
+}
+
+pub struct Sra;
+
+impl HeaderParser for Sra {
+    fn format(&self) -> HeaderFormat {
+        HeaderFormat::Sra
+    }
+
+    fn parse(&self, name: &str, comment: &str) -> Option<ReadHeader> {
+        // `SRR123.1`, `SRR123.1.2` or `SRR123.1/2`
+        let (name, read) = match name.rsplit_once('/') {
+            Some((name, read)) => (name, Some(number(read)?)),
+            None => (name, None),
+        };
+        let mut fields = name.split('.');
+        let accession = fields.next()?;
+        let spot = number(fields.next()?)?;
+        let read = match fields.next() {
+            Some(read) => Some(number(read)?),
+            None => read,
+        };
+        let archive = accession.get(..3)?;
+        if fields.next().is_some()
+            || !matches!(archive, "SRR" | "ERR" | "DRR")
+            || number::<u64>(&accession[3..]).is_none()
+        {
+            return None;
+        }
+
+        // The original name may follow, e.g. `SRR123.1 HWI-ST:8:1101:1:2/1 length=100`
+        let mut comment = comment.split_whitespace();
+        let (original, rest) = (comment.next().unwrap_or(""), comment.next().unwrap_or(""));
+        let mut header = [&Casava18 as &dyn HeaderParser, &IlluminaLegacy, &Mgi]
+            .iter()
+            .find_map(|parser| parser.parse(original, rest))
+            .map(|original| ReadHeader {
+                format: self.format(),
+                ..original
+            })
+            .unwrap_or_else(|| ReadHeader::new(self.format()));
+        header.run = Some(accession.to_string());
+        header.spot = Some(spot);
+        header.read = read.or(header.read);
+        Some(header)
+    }
+}
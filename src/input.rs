@@ -0,0 +1,151 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use flate2::bufread::MultiGzDecoder;
+
+/// Compression formats recognised from the first bytes of the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Plain,
+    Gzip,
+    Bgzf,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    /// Detect the compression format from the magic bytes at the start of the input.
+    ///
+    /// Anything that is not recognised is treated as plain text.
+    pub fn detect(head: &[u8]) -> Self {
+        if head.starts_with(&[0x1f, 0x8b]) {
+            // BGZF is gzip with a `BC` extra subfield (FLG.FEXTRA set)
+            if head.len() >= 14 && head[3] & 0x04 != 0 && &head[12..14] == b"BC" {
+                Compression::Bgzf
+            } else {
+                Compression::Gzip
+            }
+        } else if head.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else if head.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else {
+            Compression::Plain
+        }
+    }
+}
+
+/// Wrap `read` in a buffered reader that transparently decompresses it.
+///
+/// Multi-member gzip (including BGZF) and concatenated bzip2, xz and zstd
+/// streams are read to the end. A compressed stream that is cut off fails
+/// with [`io::ErrorKind::UnexpectedEof`] rather than as corrupt data.
+pub fn decompress<R>(read: R) -> io::Result<(Compression, Box<dyn BufRead + Send>)>
+where
+    R: Read + Send + 'static,
+{
+    let mut buffered = BufReader::new(read);
+    let compression = Compression::detect(buffered.fill_buf()?);
+
+    let eof = Arc::new(AtomicBool::new(false));
+    let raw = EofTracker {
+        inner: buffered,
+        eof: Arc::clone(&eof),
+    };
+    let reader: Box<dyn BufRead + Send> = match compression {
+        Compression::Plain => Box::new(raw.inner),
+        Compression::Gzip | Compression::Bgzf => Box::new(BufReader::new(Decoder {
+            inner: MultiGzDecoder::new(raw),
+            eof,
+        })),
+        Compression::Bzip2 => Box::new(BufReader::new(Decoder {
+            inner: bzip2::bufread::MultiBzDecoder::new(raw),
+            eof,
+        })),
+        Compression::Xz => Box::new(BufReader::new(Decoder {
+            inner: xz2::bufread::XzDecoder::new_multi_decoder(raw),
+            eof,
+        })),
+        Compression::Zstd => Box::new(BufReader::new(Decoder {
+            inner: zstd::stream::read::Decoder::with_buffer(raw)?,
+            eof,
+        })),
+    };
+
+    Ok((compression, reader))
+}
+
+// Raw compressed input that remembers when it has been read to the end.
+struct EofTracker<R> {
+    inner: R,
+    eof: Arc<AtomicBool>,
+}
+
+impl<R: BufRead> Read for EofTracker<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            self.eof.store(true, Ordering::Relaxed);
+        }
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for EofTracker<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let buf = self.inner.fill_buf()?;
+        if buf.is_empty() {
+            self.eof.store(true, Ordering::Relaxed);
+        }
+        Ok(buf)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
+}
+
+// Decoder that reports errors raised at the end of the raw input as truncation,
+// the decoders themselves cannot tell a cut-off stream from a corrupt one.
+struct Decoder<D> {
+    inner: D,
+    eof: Arc<AtomicBool>,
+}
+
+impl<D: Read> Read for Decoder<D> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf).map_err(|e| {
+            if self.eof.load(Ordering::Relaxed) && e.kind() != io::ErrorKind::UnexpectedEof {
+                io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("compressed stream ends early ({})", e),
+                )
+            } else {
+                e
+            }
+        })
+    }
+}
+
+/// Returns `true` if `path` is `-`, which stands for standard input.
+pub fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Open `path` (or stdin for `-`) and decompress it as needed.
+///
+/// Works with pipes such as `/dev/fd/*` from process substitution, since the
+/// compression is sniffed from the content and not from the file name.
+pub fn open(path: &Path) -> io::Result<(Compression, Box<dyn BufRead + Send>)> {
+    if is_stdin(path) {
+        decompress(io::stdin())
+    } else {
+        decompress(File::open(path)?)
+    }
+}
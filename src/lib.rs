@@ -1,8 +1,20 @@
 // src/lib.rs
+pub mod encoding;
+pub mod error;
+pub mod header;
+pub mod input;
+pub mod paired;
+pub mod reader;
+pub mod report;
 pub mod runner;
 pub mod statistics;
 pub mod utils;
 
+#[cfg(test)]
+mod tests;
+
 // Re-export important types for easier importing
-pub use runner::{FastqRecord, Statistic, WorkflowRunner};
+pub use error::{FastqError, FastqErrorKind, FastqScanError};
+pub use reader::FastqReader;
+pub use runner::{FastqRecord, RunSummary, Statistic, WorkflowRunner};
 pub use statistics::{phred_per_pos::PhredPerPos, phred_per_read::PhredPerRead};
@@ -1,14 +1,34 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use fastq_scan::{
-    runner::WorkflowRunner,
+    encoding::QualityEncoding,
+    error::FastqScanError,
+    input::is_stdin,
+    paired::{
+        MateCheck, PairedReader, PairedRunner, PairedStatistic, PairedSummary, detect_interleaved,
+    },
+    reader::FastqReader,
+    report::{self, Report},
+    runner::{RunSummary, Statistic, WorkflowRunner},
     statistics::{
-        base_count_per_pos::BaseCountPerPos, base_count_per_read::BaseCountRead,
-        phred_per_pos::PhredPerPos, phred_per_read::PhredPerRead, read_data::ReadData,
+        adapter_content::{Adapter, AdapterContent},
+        base_count_per_pos::BaseCountPerPos,
+        base_count_per_read::BaseCountRead,
+        by_lane::ByLane,
+        duplication::Duplication,
+        insert_size::InsertSize,
+        kmer_content::{self, KmerContent},
+        overrepresented::{self, Contaminant, Overrepresented},
+        phred_per_pos::PhredPerPos,
+        phred_per_read::{DEFAULT_TRACE_LEN, PhredPerRead},
+        phred_per_seq::PhredPerSeq,
+        read_data::ReadData,
+        tile_quality::{self, PerTileQuality},
     },
-    utils::process_fastq,
+    utils::{QualityMean, SampleMetadata, process_fastq, read_fasta},
 };
 use std::{
     fs::File,
+    io::{BufRead, BufWriter, Write},
     path::{Path, PathBuf},
 };
 
@@ -19,62 +39,509 @@ use std::{
     about = "Liest FASTQ-Dateien ein"
 )]
 pub struct Args {
-    /// Pfad zur R1 FASTQ-Datei
-    #[arg(short = '1', long)]
-    pub r1: PathBuf,
+    /// Pfad zur R1 FASTQ-Datei ('-' liest von stdin), mehrere Dateien
+    /// (z.B. die Lanes L001-L004 einer Probe) werden zusammengefasst
+    #[arg(short = '1', long, num_args = 1.., required = true)]
+    pub r1: Vec<PathBuf>,
+
+    /// Pfad zur R2 FASTQ-Datei (optional für Single-End, '-' liest von stdin)
+    #[arg(short = '2', long, num_args = 1..)]
+    pub r2: Vec<PathBuf>,
+
+    /// Anzahl der Threads (0 nutzt alle verfügbaren Kerne)
+    #[arg(short = 't', long, default_value_t = 1)]
+    pub threads: usize,
+
+    /// Ausgabeformat der Dateien output1 und output2
+    #[arg(short = 'f', long, value_enum, default_value_t = Format::Json)]
+    pub format: Format,
+
+    /// Zeigt die Diagramme zusätzlich mit gnuplot an
+    #[arg(long)]
+    pub plot: bool,
+
+    /// Kodierung der Qualitätswerte (33, 64 oder solexa), ohne Angabe wird sie erkannt
+    #[arg(long, value_name = "33|64|solexa")]
+    pub phred_offset: Option<QualityEncoding>,
+
+    /// Anzahl der über die ganze Eingabe verteilten Reads, deren mittlere Qualität einzeln geplottet wird
+    #[arg(long, default_value_t = DEFAULT_TRACE_LEN)]
+    pub trace_reads: usize,
+
+    /// Mittelwert der Qualitätswerte: arithmetisch oder über die Fehlerwahrscheinlichkeiten (wie bei ONT und PacBio)
+    #[arg(long, value_enum, default_value_t = QualityMean::Arithmetic)]
+    pub quality_mean: QualityMean,
+
+    /// Qualitätsgrenzen, für die der Anteil der Reads mit geringerer mittlerer Qualität berichtet wird
+    #[arg(long, value_delimiter = ',', default_values_t = [20, 30])]
+    pub quality_cutoffs: Vec<u8>,
+
+    /// Anteil an allen Reads, ab dem eine Sequenz als überrepräsentiert gilt
+    #[arg(long, default_value_t = overrepresented::DEFAULT_THRESHOLD)]
+    pub overrep_threshold: f64,
+
+    /// FASTA-Datei mit weiteren Kontaminanten für überrepräsentierte Sequenzen
+    #[arg(long)]
+    pub contaminants: Option<PathBuf>,
+
+    /// Länge der k-mere, deren Anreicherung an bestimmten Positionen gesucht wird
+    #[arg(
+        long,
+        default_value_t = kmer_content::DEFAULT_KMER_SIZE,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new()
+            .range(1..=kmer_content::MAX_KMER_SIZE as u64)
+    )]
+    pub kmer_size: usize,
+
+    /// Abweichung von der mittleren Qualität einer Position, ab der eine Tile gemeldet wird
+    #[arg(long, default_value_t = tile_quality::DEFAULT_TILE_THRESHOLD)]
+    pub tile_threshold: f64,
+
+    /// Zusätzlicher Adapter als NAME=SEQUENZ (mehrfach möglich)
+    #[arg(long = "adapter", value_name = "NAME=SEQ")]
+    pub adapters: Vec<Adapter>,
+
+    /// FASTA-Datei mit weiteren Adaptern
+    #[arg(long)]
+    pub adapter_fasta: Option<PathBuf>,
+
+    // Contents of `contaminants`, read once before the scan
+    #[arg(skip)]
+    pub contaminant_list: Vec<Contaminant>,
+
+    /// Liest R1 und R2 paarweise und prüft, ob die Read-Namen zusammenpassen
+    #[arg(long, requires = "r2")]
+    pub paired: bool,
+
+    /// Die R1-Dateien enthalten R1 und R2 abwechselnd (auto erkennt dies an den Read-Namen)
+    #[arg(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        default_value_t = Interleaved::No,
+        default_missing_value = "yes",
+        conflicts_with = "r2"
+    )]
+    pub interleaved: Interleaved,
+
+    /// Umgang mit Reads ohne passenden Mate im Paired-Modus
+    #[arg(long, value_enum, default_value_t = MateCheck::Strict)]
+    pub mate_check: MateCheck,
+
+    /// Berichtet alle Statistiken zusätzlich getrennt nach Flowcell und Lane
+    #[arg(long)]
+    pub by_lane: bool,
+
+    /// Schreibt zusätzlich die Rohdaten (z.B. Zählwerte) jeder Statistik ins JSON
+    #[arg(long)]
+    pub raw: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Format {
+    Json,
+    Tsv,
+    Text,
+    Html,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interleaved {
+    No,
+    Yes,
+    Auto,
+}
 
-    /// Pfad zur R2 FASTQ-Datei (optional für Single-End)
-    #[arg(short = '2', long)]
-    pub r2: Option<PathBuf>,
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Tsv => "tsv",
+            Format::Text => "txt",
+            Format::Html => "html",
+        }
+    }
 }
 
 fn main() {
     // Parse command line arguments
     let args = Args::parse();
 
+    // stdin can only be consumed once
+    if args
+        .r1
+        .iter()
+        .chain(&args.r2)
+        .filter(|p| is_stdin(p))
+        .count()
+        > 1
+    {
+        eprintln!("Fehler: Nur eine der Read-Dateien kann von stdin gelesen werden!");
+        std::process::exit(1);
+    }
+
+    let mut args = args;
+    if args.threads == 0 {
+        args.threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    }
+
+    if let Some(path) = &args.contaminants {
+        args.contaminant_list = load_fasta(path, "Kontaminanten")
+            .iter()
+            .map(|(name, seq)| Contaminant::new(name, seq))
+            .collect();
+    }
+    if let Some(path) = &args.adapter_fasta {
+        for (name, seq) in load_fasta(path, "Adapter") {
+            match Adapter::parse(&name, &seq) {
+                Ok(adapter) => args.adapters.push(adapter),
+                Err(e) => {
+                    eprintln!(
+                        "Fehler in Adapter-Datei {:?}, Eintrag '{}': {}",
+                        path, name, e
+                    );
+                    std::process::exit(2);
+                }
+            }
+        }
+    }
+
+    // The R1 files opened for the detection are read from there on
+    let (interleaved, opened) = match args.interleaved {
+        Interleaved::No => (false, Vec::new()),
+        Interleaved::Yes => (true, Vec::new()),
+        Interleaved::Auto => detect_interleaved_files(&args),
+    };
+
+    if args.paired || interleaved {
+        if !interleaved && args.r1.len() != args.r2.len() {
+            eprintln!(
+                "Fehler: Im Paired-Modus werden gleich viele R1- und R2-Dateien benötigt ({} und {})!",
+                args.r1.len(),
+                args.r2.len()
+            );
+            std::process::exit(1);
+        }
+        if let Err((index, e)) = process_pairs(&args, interleaved, opened) {
+            match args.r2.get(index) {
+                Some(r2) => eprintln!("Fehler im Read-Paar {:?} / {:?}: {}", args.r1[index], r2, e),
+                None => eprintln!("Fehler in {:?}: {}", args.r1[index], e),
+            }
+            std::process::exit(e.exit_code());
+        }
+        println!("\n\nFertig. Exiting now!");
+        return;
+    }
+
     // Calling for Arg 1 and possibly Arg2
-    process_file(&args.r1, 1);
-    if let Some(read2_path) = args.r2 {
-        process_file(&read2_path, 2);
+    let mut inputs = vec![(&args.r1, 1, opened)];
+    if !args.r2.is_empty() {
+        inputs.push((&args.r2, 2, Vec::new()));
+    }
+    for (paths, number, opened) in inputs {
+        if let Err((path, e)) = process_files(paths, number, &args, opened) {
+            eprintln!("Fehler in Read{} {:?}: {}", number, path, e);
+            std::process::exit(e.exit_code());
+        }
     }
 
     println!("\n\nFertig. Exiting now!");
 }
 
-fn process_file(path: &PathBuf, number: u8) {
-    // Check if the file exists
-    if !Path::new(path).exists() {
-        eprintln!("Fehler: Die Read{}-'{:?}' existiert nicht!", number, path);
-        std::process::exit(1);
-    } else {
-        println!("\nRead{}-Datei: {:?}", number, path);
+// Reads a FASTA file given on the command line, exits if that fails.
+fn load_fasta(path: &Path, kind: &str) -> Vec<(String, Vec<u8>)> {
+    read_fasta(path).unwrap_or_else(|source| {
+        let e = FastqScanError::Open {
+            path: path.to_path_buf(),
+            source,
+        };
+        eprintln!("Fehler in {}-Datei: {}", kind, e);
+        std::process::exit(e.exit_code());
+    })
+}
+
+// The statistics computed for every input.
+fn new_statistics(args: &Args) -> Vec<Box<dyn Statistic>> {
+    let mut statistics: Vec<Box<dyn Statistic>> = vec![
+        Box::new(BaseCountPerPos::new()),
+        Box::new(BaseCountRead::new()),
+        Box::new(PhredPerPos::new().with_mean(args.quality_mean)),
+        Box::new(PhredPerRead::with_trace_len(args.trace_reads).with_mean(args.quality_mean)),
+        Box::new(PhredPerSeq::with_cutoffs(&args.quality_cutoffs)),
+        Box::new(ReadData::new()),
+        Box::new(PerTileQuality::with_threshold(args.tile_threshold)),
+        Box::new(Duplication::new()),
+        Box::new(KmerContent::with_kmer_size(args.kmer_size)),
+        Box::new(AdapterContent::with_adapters(
+            Adapter::builtin()
+                .into_iter()
+                .chain(args.adapters.iter().cloned())
+                .collect(),
+        )),
+        Box::new(
+            Overrepresented::with_threshold(args.overrep_threshold)
+                .with_contaminants(args.contaminant_list.clone()),
+        ),
+    ];
+    if args.by_lane {
+        let template = statistics.iter().map(|s| s.empty()).collect();
+        statistics.push(Box::new(ByLane::new(template)));
     }
+    statistics
+}
 
-    // Create the runner
-    let mut runn = WorkflowRunner {
-        statistics: vec![
-            Box::new(BaseCountPerPos::new()),
-            Box::new(BaseCountRead::new()),
-            Box::new(PhredPerPos::new()),
-            Box::new(PhredPerRead::new()),
-            Box::new(ReadData::new()),
-        ],
+// Scans the files one after the other into the same statistics and writes one output.
+fn process_files(
+    paths: &[PathBuf],
+    number: u8,
+    args: &Args,
+    opened: Vec<Input>,
+) -> Result<RunSummary, (PathBuf, FastqScanError)> {
+    let mut total = WorkflowRunner {
+        statistics: new_statistics(args),
     };
+    let mut summary = RunSummary::default();
 
-    // Process the FASTQ file
-    println!("Processing {:?}...", path);
-    runn.process(process_fastq(path.to_path_buf()));
+    // All files go through the same statistics, merging separate runs would
+    // lose counts of statistics that only track the first sequences
+    let mut opened = opened.into_iter();
+    for path in paths {
+        let file_summary = process_file(&mut total, path, number, args, opened.next())
+            .map_err(|e| (path.clone(), e))?;
+        summary.merge(&file_summary);
+    }
 
-    println!("Read has been processed! Printing to file...");
+    println!(
+        "Read{} has been processed ({} reads, {} bases)! Printing to file...",
+        number, summary.records, summary.bases
+    );
 
     // get statistics back
-    let stats = runn.finalize();
+    let stats = total.finalize();
+    let mut reports = vec![summary_report(&summary, paths)];
+    reports.extend(statistic_reports(&stats, args));
+    write_reports(&number.to_string(), &reports, args);
+
+    Ok(summary)
+}
+
+// The overview at the start of every output.
+fn summary_report(summary: &RunSummary, paths: &[PathBuf]) -> Report {
+    let encoding = summary.encoding.map_or("-".to_string(), |e| e.to_string());
+    Report::new("Summary")
+        .with_metric("Reads", summary.records)
+        .with_metric("Bases", summary.bases)
+        .with_metric("Quality encoding", encoding)
+        .with_samples(paths.iter().map(|p| SampleMetadata::from_path(p)).collect())
+}
+
+// The reports of all statistics, with the raw data if requested.
+fn statistic_reports(statistics: &[Box<dyn Statistic>], args: &Args) -> Vec<Report> {
+    let mut reports = Vec::new();
+    for s in statistics {
+        let mut stat_reports = s.reports();
+        if args.raw {
+            stat_reports[0].raw =
+                Some(serde_json::to_value(s).expect("Failed to serialize statistic"));
+        }
+        reports.extend(stat_reports);
+    }
+    reports
+}
 
+// Writes the reports to `output{suffix}` in the requested format, exits if that fails.
+fn write_reports(suffix: &str, reports: &[Report], args: &Args) {
     // Create output file
-    let mut file = File::create(format!("output{}.json", number)).expect("Unable to create file");
+    let name = format!("output{}.{}", suffix, args.format.extension());
+    let written = File::create(&name).and_then(|file| {
+        let mut file = BufWriter::new(file);
+        // Write the reports in the requested format
+        match args.format {
+            Format::Json => report::write_json(&mut file, reports),
+            Format::Tsv => report::write_tsv(&mut file, reports),
+            Format::Text => report::write_text(&mut file, reports),
+            Format::Html => report::write_html(&mut file, reports),
+        }?;
+        file.flush()
+    });
+    if let Err(source) = written {
+        let e = FastqScanError::Write {
+            path: PathBuf::from(&name),
+            source,
+        };
+        eprintln!("Fehler beim Schreiben der Ausgabe: {}", e);
+        std::process::exit(e.exit_code());
+    }
 
-    // Serialize the statistics to Json
-    serde_json::to_writer_pretty(&mut file, &stats).expect("Failed to write to Json");
+    println!("Finished printing to file {}", name);
 
-    println!("Finished printing to file output{}.json", number);
+    if args.plot {
+        report::plot(reports);
+    }
+}
+
+// The statistics computed from both mates of every pair.
+fn new_paired_statistics(args: &Args) -> Vec<Box<dyn PairedStatistic>> {
+    vec![Box::new(InsertSize::with_adapters(
+        Adapter::builtin()
+            .into_iter()
+            .chain(args.adapters.iter().cloned())
+            .collect(),
+    ))]
+}
+
+// Records of an input, in the encoding given on the command line.
+type Input = FastqReader<Box<dyn BufRead + Send>>;
+
+fn open_fastq(path: &Path, args: &Args) -> Result<Input, FastqScanError> {
+    let read = process_fastq(path.to_path_buf())?;
+    Ok(FastqReader::with_encoding(read, args.phred_offset))
+}
+
+// Checks for `--interleaved auto` whether the R1 files are interleaved,
+// exits if that cannot be told or the files disagree. Returns the opened
+// files, which replay the records looked at, so pipes and stdin work as well.
+fn detect_interleaved_files(args: &Args) -> (bool, Vec<Input>) {
+    let mut detected = Vec::new();
+    let mut opened = Vec::new();
+    for path in &args.r1 {
+        match open_fastq(path, args) {
+            Ok(mut reader) => {
+                detected.push(detect_interleaved(&mut reader));
+                opened.push(reader);
+            }
+            Err(e) => {
+                eprintln!("Fehler in Read1 {:?}: {}", path, e);
+                std::process::exit(e.exit_code());
+            }
+        }
+    }
+    if detected.iter().any(|&d| d != detected[0]) {
+        eprintln!("Fehler: Nur ein Teil der R1-Dateien ist interleaved!");
+        std::process::exit(1);
+    }
+    if detected[0] {
+        println!("R1-Dateien sind interleaved, R1 und R2 werden getrennt ausgewertet");
+    }
+    (detected[0], opened)
+}
+
+// Scans the R1 and R2 files pairwise, or the interleaved R1 files, and writes
+// the statistics of each mate and of the pairs into separate outputs.
+// Errors come with the index of the pair of files they occurred in.
+fn process_pairs(
+    args: &Args,
+    interleaved: bool,
+    opened: Vec<Input>,
+) -> Result<PairedSummary, (usize, FastqScanError)> {
+    let mut total = PairedRunner {
+        r1: new_statistics(args),
+        r2: new_statistics(args),
+        pairs: new_paired_statistics(args),
+    };
+    let mut summary = PairedSummary::default();
+
+    let mut opened = opened.into_iter();
+    for (index, r1) in args.r1.iter().enumerate() {
+        let error = |e| (index, e);
+        let r1_reader = match opened.next() {
+            Some(reader) => reader,
+            None => open_fastq(r1, args).map_err(error)?,
+        };
+        let reader = match args.r2.get(index) {
+            Some(r2) if !interleaved => {
+                println!("\nRead-Paar: {:?} / {:?}", r1, r2);
+                let r2_reader = open_fastq(r2, args).map_err(error)?;
+                PairedReader::from_records(r1_reader, r2_reader, args.mate_check)
+            }
+            _ => {
+                println!("\nInterleaved-Datei: {:?}", r1);
+                PairedReader::interleaved_records(r1_reader, args.mate_check)
+            }
+        };
+        // One runner for all files, as in `process_files`
+        let pair_summary = total
+            .process_parallel(reader, args.threads)
+            .map_err(error)?;
+        summary.merge(&pair_summary);
+    }
+
+    let (unpaired_r1, unpaired_r2) = summary.unpaired();
+    println!(
+        "Read pairs have been processed ({} pairs)! Printing to file...",
+        summary.pairs
+    );
+    if summary.name_mismatches > 0 {
+        eprintln!(
+            "Warnung: {} Paare mit unterschiedlichen Read-Namen",
+            summary.name_mismatches
+        );
+    }
+    if unpaired_r1 + unpaired_r2 > 0 {
+        eprintln!(
+            "Warnung: {} Reads in R1 und {} Reads in R2 ohne Mate",
+            unpaired_r1, unpaired_r2
+        );
+    }
+
+    // Both mates come from the same files if they are interleaved
+    let r2_paths = if interleaved { &args.r1 } else { &args.r2 };
+    for (number, paths, mate_summary, statistics) in [
+        (1, &args.r1, &summary.r1, &total.r1),
+        (2, r2_paths, &summary.r2, &total.r2),
+    ] {
+        let mut reports = vec![summary_report(mate_summary, paths)];
+        reports.extend(statistic_reports(statistics, args));
+        write_reports(&number.to_string(), &reports, args);
+    }
+
+    let mut reports = vec![
+        Report::new("PairSummary")
+            .with_metric("Pairs", summary.pairs)
+            .with_metric("Name mismatches", summary.name_mismatches)
+            .with_metric("Unpaired R1", unpaired_r1)
+            .with_metric("Unpaired R2", unpaired_r2)
+            .with_samples(
+                args.r1
+                    .iter()
+                    .chain(&args.r2)
+                    .map(|p| SampleMetadata::from_path(p))
+                    .collect(),
+            ),
+    ];
+    for s in &total.pairs {
+        let mut stat_reports = s.reports();
+        if args.raw {
+            stat_reports[0].raw =
+                Some(serde_json::to_value(s).expect("Failed to serialize statistic"));
+        }
+        reports.extend(stat_reports);
+    }
+    write_reports("_pairs", &reports, args);
+
+    Ok(summary)
+}
+
+// Scans one file into the statistics of `runn`, from `opened` if it is
+// already open.
+fn process_file(
+    runn: &mut WorkflowRunner,
+    path: &PathBuf,
+    number: u8,
+    args: &Args,
+    opened: Option<Input>,
+) -> Result<RunSummary, FastqScanError> {
+    if is_stdin(path) {
+        println!("\nRead{}-Datei: stdin", number);
+    } else {
+        println!("\nRead{}-Datei: {:?}", number, path);
+    }
+
+    // Process the FASTQ file
+    println!("Processing {:?}...", path);
+    let reader = match opened {
+        Some(reader) => reader,
+        None => open_fastq(path, args)?,
+    };
+    runn.process_records_parallel(reader, args.threads)
 }
@@ -0,0 +1,372 @@
+use std::any::Any;
+use std::io::BufRead;
+
+use serde::{Deserialize, Serialize};
+
+use crate::encoding::QualityEncoding;
+use crate::error::{FastqScanError, PairError, PairErrorKind};
+use crate::reader::FastqReader;
+use crate::report::Report;
+use crate::runner::{
+    FastqRecord, Partial, RunSummary, Statistic, merge_statistics, process_chunks,
+};
+
+/// A statistic computed from both mates of a read pair.
+#[typetag::serde(tag = "type")]
+pub trait PairedStatistic: Any + Send {
+    fn process(&mut self, r1: &FastqRecord, r2: &FastqRecord);
+
+    /// The results in the representation shared by all output formats.
+    fn report(&self) -> Report;
+
+    /// All reports of the statistic, see [`Statistic::reports`].
+    fn reports(&self) -> Vec<Report> {
+        vec![self.report()]
+    }
+
+    /// Add the results of `other`, a statistic of the same type, to `self`.
+    fn merge(&mut self, other: &dyn PairedStatistic);
+
+    /// A new statistic of the same type and configuration that has not seen any pairs.
+    fn empty(&self) -> Box<dyn PairedStatistic>;
+}
+
+/// Downcast `other` to the type of the paired statistic it is merged into.
+///
+/// Panics if the types differ, see [`crate::runner::downcast`].
+pub fn downcast_paired<T: PairedStatistic>(other: &dyn PairedStatistic) -> &T {
+    let any: &dyn Any = other;
+    any.downcast_ref::<T>().unwrap_or_else(|| {
+        panic!(
+            "Cannot merge {} into {}",
+            other.typetag_name(),
+            std::any::type_name::<T>()
+        )
+    })
+}
+
+/// What to do with records that are not proper mates.
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MateCheck {
+    /// Stop at the first mismatching name or when one file ends early.
+    #[default]
+    Strict,
+    /// Count mismatching names and unpaired records and go on.
+    Warn,
+}
+
+/// The read name without a trailing `/1` or `/2`, as compared between mates.
+pub fn mate_name(name: &[u8]) -> &[u8] {
+    match name {
+        [rest @ .., b'/', b'1' | b'2'] => rest,
+        _ => name,
+    }
+}
+
+/// Totals of a paired run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PairedSummary {
+    pub r1: RunSummary,
+    pub r2: RunSummary,
+    /// Records read together from both files.
+    pub pairs: u64,
+    /// Pairs whose read names do not match.
+    pub name_mismatches: u64,
+}
+
+impl PairedSummary {
+    /// Records of R1 and R2 without a mate in the other file.
+    pub fn unpaired(&self) -> (u64, u64) {
+        (self.r1.records - self.pairs, self.r2.records - self.pairs)
+    }
+
+    pub fn merge(&mut self, other: &PairedSummary) {
+        self.r1.merge(&other.r1);
+        self.r2.merge(&other.r2);
+        self.pairs += other.pairs;
+        self.name_mismatches += other.name_mismatches;
+    }
+}
+
+/// The records read together from R1 and R2.
+///
+/// Both are present unless one file has ended before the other, which is
+/// only passed on with [`MateCheck::Warn`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Mates {
+    pub r1: Option<FastqRecord>,
+    pub r2: Option<FastqRecord>,
+}
+
+// Where the mates are read from.
+enum Source<R1, R2> {
+    Files(FastqReader<R1>, FastqReader<R2>),
+    // R1 and R2 records alternate in one input
+    Interleaved(FastqReader<R1>),
+}
+
+/// Reads R1 and R2 in lockstep and checks that the records are mates.
+///
+/// The mates come from two files, or alternate in a single interleaved input.
+pub struct PairedReader<R1, R2> {
+    source: Source<R1, R2>,
+    check: MateCheck,
+    summary: PairedSummary,
+}
+
+// Read the next record of one mate, errors are marked with the mate.
+fn read_mate<R: BufRead>(
+    reader: &mut FastqReader<R>,
+    mate: u8,
+) -> Result<Option<FastqRecord>, FastqScanError> {
+    let mut record = FastqRecord::default();
+    match reader.read_record(&mut record) {
+        Ok(true) => Ok(Some(record)),
+        Ok(false) => Ok(None),
+        Err(e) => Err(FastqScanError::Mate {
+            mate,
+            source: Box::new(e.into()),
+        }),
+    }
+}
+
+// Number of records looked at by `detect_interleaved`.
+const DETECT_RECORDS: usize = 16;
+
+/// Whether the records of `reader` look interleaved, judged from its first
+/// records: every R1 record is followed by a record of the same name (after
+/// removing `/1` and `/2`), and consecutive pairs have different names.
+///
+/// The records looked at are read again afterwards, so this works on pipes.
+pub fn detect_interleaved<R: BufRead>(reader: &mut FastqReader<R>) -> bool {
+    let records = reader.peek(DETECT_RECORDS);
+    if records.len() < 2 || !records.len().is_multiple_of(2) {
+        return false;
+    }
+    let names: Vec<&[u8]> = records.iter().map(|r| mate_name(&r.name)).collect();
+    let pairs: Vec<&[&[u8]]> = names.chunks(2).collect();
+    pairs.iter().all(|pair| pair[0] == pair[1]) && pairs.windows(2).all(|w| w[0][0] != w[1][0])
+}
+
+impl<R> PairedReader<R, R>
+where
+    R: BufRead,
+{
+    /// Read the mates from one input in which R1 and R2 records alternate.
+    pub fn interleaved(read: R, check: MateCheck) -> Self {
+        PairedReader::interleaved_records(FastqReader::new(read), check)
+    }
+
+    /// Read the mates from the records of `reader`, see [`PairedReader::interleaved`].
+    pub fn interleaved_records(reader: FastqReader<R>, check: MateCheck) -> Self {
+        PairedReader {
+            source: Source::Interleaved(reader),
+            check,
+            summary: PairedSummary::default(),
+        }
+    }
+}
+
+impl<R1, R2> PairedReader<R1, R2>
+where
+    R1: BufRead,
+    R2: BufRead,
+{
+    pub fn new(r1: R1, r2: R2, check: MateCheck) -> Self {
+        PairedReader::from_records(FastqReader::new(r1), FastqReader::new(r2), check)
+    }
+
+    /// Read the mates from the records of two readers, e.g. ones with a given
+    /// quality encoding.
+    pub fn from_records(r1: FastqReader<R1>, r2: FastqReader<R2>, check: MateCheck) -> Self {
+        PairedReader {
+            source: Source::Files(r1, r2),
+            check,
+            summary: PairedSummary::default(),
+        }
+    }
+
+    /// Read qualities in `encoding` instead of detecting it for every file.
+    pub fn with_encoding(mut self, encoding: Option<QualityEncoding>) -> Self {
+        match &mut self.source {
+            Source::Files(r1, r2) => {
+                r1.set_encoding(encoding);
+                r2.set_encoding(encoding);
+            }
+            Source::Interleaved(read) => read.set_encoding(encoding),
+        }
+        self
+    }
+
+    /// Totals of the records read so far.
+    pub fn summary(&self) -> PairedSummary {
+        let mut summary = self.summary;
+        (summary.r1.encoding, summary.r2.encoding) = match &self.source {
+            Source::Files(r1, r2) => (r1.encoding(), r2.encoding()),
+            Source::Interleaved(read) => (read.encoding(), read.encoding()),
+        };
+        summary
+    }
+
+    /// Read the next pair, `None` once both files have ended.
+    pub fn read_mates(&mut self) -> Result<Option<Mates>, FastqScanError> {
+        let mates = match &mut self.source {
+            Source::Files(r1, r2) => Mates {
+                r1: read_mate(r1, 1)?,
+                r2: read_mate(r2, 2)?,
+            },
+            Source::Interleaved(read) => Mates {
+                r1: read_mate(read, 1)?,
+                r2: read_mate(read, 2)?,
+            },
+        };
+        let strict = self.check == MateCheck::Strict;
+        let error = |kind| {
+            Err(FastqScanError::Pairing(PairError {
+                kind,
+                pair: self.summary.pairs,
+            }))
+        };
+        match (&mates.r1, &mates.r2) {
+            (None, None) => return Ok(None),
+            (Some(r1), Some(r2)) => {
+                if mate_name(&r1.name) != mate_name(&r2.name) {
+                    if strict {
+                        return error(PairErrorKind::NameMismatch {
+                            r1: r1.name_str().into_owned(),
+                            r2: r2.name_str().into_owned(),
+                        });
+                    }
+                    self.summary.name_mismatches += 1;
+                }
+                self.summary.pairs += 1;
+            }
+            (None, Some(_)) if strict => return error(PairErrorKind::MissingMate { mate: 1 }),
+            (Some(_), None) if strict => return error(PairErrorKind::MissingMate { mate: 2 }),
+            _ => {}
+        }
+        for (record, summary) in [
+            (&mates.r1, &mut self.summary.r1),
+            (&mates.r2, &mut self.summary.r2),
+        ] {
+            if let Some(record) = record {
+                summary.records += 1;
+                summary.bases += record.seq.len() as u64;
+            }
+        }
+        Ok(Some(mates))
+    }
+}
+
+/// Computes statistics of both mates and of the pairs.
+pub struct PairedRunner {
+    pub r1: Vec<Box<dyn Statistic>>,
+    pub r2: Vec<Box<dyn Statistic>>,
+    pub pairs: Vec<Box<dyn PairedStatistic>>,
+}
+
+impl Partial for PairedRunner {
+    type Item = Mates;
+
+    fn empty(&self) -> Self {
+        PairedRunner::empty(self)
+    }
+
+    fn add(&mut self, mates: &Mates) {
+        for (record, statistics) in [(&mates.r1, &mut self.r1), (&mates.r2, &mut self.r2)] {
+            if let Some(record) = record {
+                statistics.add(record);
+            }
+        }
+        if let Mates {
+            r1: Some(r1),
+            r2: Some(r2),
+        } = mates
+        {
+            for statistic in self.pairs.iter_mut() {
+                statistic.process(r1, r2);
+            }
+        }
+    }
+
+    // The paired statistics do not depend on the order of the pairs
+    fn take_ordered(&mut self) -> Self {
+        PairedRunner {
+            r1: self.r1.take_ordered(),
+            r2: self.r2.take_ordered(),
+            pairs: self.pairs.iter().map(|s| s.empty()).collect(),
+        }
+    }
+
+    fn merge_part(&mut self, other: &Self, ordered: bool) {
+        self.r1.merge_part(&other.r1, ordered);
+        self.r2.merge_part(&other.r2, ordered);
+        if !ordered {
+            for (statistic, other) in self.pairs.iter_mut().zip(&other.pairs) {
+                statistic.merge(other.as_ref());
+            }
+        }
+    }
+}
+
+impl PairedRunner {
+    /// Process all pairs of `reader`.
+    ///
+    /// Stops at the first error in either file and, with [`MateCheck::Strict`],
+    /// at the first records that are not mates.
+    pub fn process<R1, R2>(
+        &mut self,
+        mut reader: PairedReader<R1, R2>,
+    ) -> Result<PairedSummary, FastqScanError>
+    where
+        R1: BufRead,
+        R2: BufRead,
+    {
+        while let Some(mates) = reader.read_mates()? {
+            self.add(&mates);
+        }
+        Ok(reader.summary())
+    }
+
+    /// Process all pairs of `reader` on `threads` worker threads, with the
+    /// same result as [`PairedRunner::process`].
+    pub fn process_parallel<R1, R2>(
+        &mut self,
+        mut reader: PairedReader<R1, R2>,
+        threads: usize,
+    ) -> Result<PairedSummary, FastqScanError>
+    where
+        R1: BufRead + Send,
+        R2: BufRead + Send,
+    {
+        if threads <= 1 {
+            return self.process(reader);
+        }
+        process_chunks(self, threads, &mut || reader.read_mates())?;
+        Ok(reader.summary())
+    }
+
+    /// A runner with empty copies of the statistics of this runner.
+    pub fn empty(&self) -> PairedRunner {
+        PairedRunner {
+            r1: self.r1.iter().map(|s| s.empty()).collect(),
+            r2: self.r2.iter().map(|s| s.empty()).collect(),
+            pairs: self.pairs.iter().map(|s| s.empty()).collect(),
+        }
+    }
+
+    /// Merge the statistics of `other`, which must hold the same kinds of
+    /// statistics in the same order, into this runner.
+    pub fn merge(&mut self, other: &PairedRunner) {
+        assert_eq!(
+            self.pairs.len(),
+            other.pairs.len(),
+            "Cannot merge runners with different statistics"
+        );
+        merge_statistics(&mut self.r1, &other.r1);
+        merge_statistics(&mut self.r2, &other.r2);
+        for (statistic, other) in self.pairs.iter_mut().zip(&other.pairs) {
+            statistic.merge(other.as_ref());
+        }
+    }
+}
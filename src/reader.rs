@@ -0,0 +1,286 @@
+use std::collections::VecDeque;
+use std::io::BufRead;
+
+use crate::encoding::QualityEncoding;
+use crate::error::{FastqError, FastqErrorKind};
+use crate::runner::FastqRecord;
+
+// The quality encoding is detected from the first records, up to this many
+// records or bases, whichever is reached first.
+const ENCODING_SAMPLE_RECORDS: usize = 10_000;
+const ENCODING_SAMPLE_BASES: usize = 1_000_000;
+
+/// Strict reader for 4-line FASTQ records.
+///
+/// Keeps track of the record index, line number and byte offset so that
+/// errors point to the exact location of the problem.
+///
+/// Quality lines are converted to Phred+33. Unless the encoding is given, it
+/// is detected from the first records, which are read ahead for this.
+pub struct FastqReader<R> {
+    read: R,
+    buffer: Vec<u8>,
+    header: Vec<u8>,
+    record: u64,
+    line: u64,
+    offset: u64,
+    consumed: u64,
+    terminated: bool,
+    // `None` until detected
+    encoding: Option<QualityEncoding>,
+    // Records read ahead to detect the encoding, and the error that ended them
+    sample: VecDeque<FastqRecord>,
+    sample_error: Option<FastqError>,
+}
+
+impl<R> FastqReader<R>
+where
+    R: BufRead,
+{
+    pub fn new(read: R) -> Self {
+        FastqReader::with_encoding(read, None)
+    }
+
+    /// Read qualities in `encoding`, or detect it if it is `None`.
+    pub fn with_encoding(read: R, encoding: Option<QualityEncoding>) -> Self {
+        FastqReader {
+            read,
+            buffer: Vec::new(),
+            header: Vec::new(),
+            record: 0,
+            line: 0,
+            offset: 0,
+            consumed: 0,
+            terminated: true,
+            encoding,
+            sample: VecDeque::new(),
+            sample_error: None,
+        }
+    }
+
+    /// Number of records read successfully so far.
+    pub fn records_read(&self) -> u64 {
+        self.record - self.sample.len() as u64
+    }
+
+    // Use `encoding` instead of detecting it, before the first record is read.
+    pub(crate) fn set_encoding(&mut self, encoding: Option<QualityEncoding>) {
+        self.encoding = encoding;
+    }
+
+    /// The quality encoding, `None` before the first record is read if it is detected.
+    pub fn encoding(&self) -> Option<QualityEncoding> {
+        self.encoding
+    }
+
+    /// The next `n` records, fewer at the end of the input or before a
+    /// malformed record. They are read ahead and returned again by
+    /// [`FastqReader::read_record`], which also reports the error.
+    pub fn peek(&mut self, n: usize) -> &[FastqRecord] {
+        if self.encoding.is_none() {
+            self.detect_encoding();
+        }
+        let encoding = self.encoding.expect("Encoding is known after detection");
+        while self.sample.len() < n && self.sample_error.is_none() {
+            let mut record = FastqRecord::default();
+            match self.parse_record(&mut record) {
+                Ok(true) => {
+                    encoding.normalize(&mut record.qual);
+                    self.sample.push_back(record);
+                }
+                Ok(false) => break,
+                Err(error) => self.sample_error = Some(error),
+            }
+        }
+        let sample = self.sample.make_contiguous();
+        &sample[..n.min(sample.len())]
+    }
+
+    /// Read the next record into `record`.
+    ///
+    /// Returns `Ok(false)` on a clean end of input, i.e. when the input ends
+    /// between two records.
+    pub fn read_record(&mut self, record: &mut FastqRecord) -> Result<bool, FastqError> {
+        let Some(encoding) = self.encoding else {
+            self.detect_encoding();
+            return self.read_record(record);
+        };
+        if let Some(sampled) = self.sample.pop_front() {
+            *record = sampled;
+            return Ok(true);
+        }
+        if let Some(error) = self.sample_error.take() {
+            return Err(error);
+        }
+        if !self.parse_record(record)? {
+            return Ok(false);
+        }
+        encoding.normalize(&mut record.qual);
+        Ok(true)
+    }
+
+    // Read ahead the first records and detect the encoding from them.
+    fn detect_encoding(&mut self) {
+        let mut bases = 0;
+        while self.sample.len() < ENCODING_SAMPLE_RECORDS && bases < ENCODING_SAMPLE_BASES {
+            let mut record = FastqRecord::default();
+            match self.parse_record(&mut record) {
+                Ok(true) => {
+                    bases += record.qual.len();
+                    self.sample.push_back(record);
+                }
+                Ok(false) => break,
+                Err(error) => {
+                    self.sample_error = Some(error);
+                    break;
+                }
+            }
+        }
+        let encoding = QualityEncoding::detect_from(self.sample.iter().map(|r| &r.qual[..]));
+        for record in self.sample.iter_mut() {
+            encoding.normalize(&mut record.qual);
+        }
+        self.encoding = Some(encoding);
+    }
+
+    // Parse the next record into `record`, without converting the qualities.
+    fn parse_record(&mut self, record: &mut FastqRecord) -> Result<bool, FastqError> {
+        // Header line (line 1), blank lines between records are tolerated
+        loop {
+            if !self.next_line()? {
+                return Ok(false);
+            }
+            if !self.buffer.is_empty() {
+                break;
+            }
+        }
+        if self.buffer[0] != b'@' {
+            return Err(self.error(FastqErrorKind::MissingHeader {
+                found: self.buffer[0],
+            }));
+        }
+        self.header.clear();
+        self.header.extend_from_slice(&self.buffer[1..]);
+        let name = header_name(&self.header);
+        record.name.clear();
+        record.name.extend_from_slice(name);
+        record.comment.clear();
+        record
+            .comment
+            .extend_from_slice(self.header[name.len()..].trim_ascii_start());
+
+        // Sequence line (line 2)
+        self.expect_line()?;
+        if let Some(column) = self.buffer.iter().position(|&b| !is_valid_base(b)) {
+            return Err(self.error(FastqErrorKind::InvalidBase {
+                base: self.buffer[column],
+                column: column + 1,
+            }));
+        }
+        record.seq.clear();
+        record.seq.extend_from_slice(&self.buffer);
+
+        // Separator line (line 3), optionally repeating the read name
+        self.expect_line()?;
+        match self.buffer.first() {
+            Some(b'+') => {}
+            Some(&found) => return Err(self.error(FastqErrorKind::MissingSeparator { found })),
+            None => return Err(self.error(FastqErrorKind::MissingSeparator { found: b'\n' })),
+        }
+        let repeated = &self.buffer[1..];
+        if !repeated.is_empty() && repeated != self.header && repeated != record.name {
+            return Err(self.error(FastqErrorKind::NameMismatch {
+                header: String::from_utf8_lossy(&self.header).into_owned(),
+                separator: String::from_utf8_lossy(repeated).into_owned(),
+            }));
+        }
+
+        // Quality line (line 4)
+        self.expect_line()?;
+        let min = self.encoding.map_or(b'!', |e| e.min_char());
+        if let Some(column) = self.buffer.iter().position(|&q| !(min..=b'~').contains(&q)) {
+            let (qual, column) = (self.buffer[column], column + 1);
+            return Err(self.error(match self.encoding {
+                Some(encoding) if (b'!'..=b'~').contains(&qual) => {
+                    FastqErrorKind::QualityOutOfRange {
+                        qual,
+                        column,
+                        encoding,
+                    }
+                }
+                _ => FastqErrorKind::InvalidQuality { qual, column },
+            }));
+        }
+        if self.buffer.len() < record.seq.len() && !self.terminated {
+            // The last line was cut off
+            return Err(self.error(FastqErrorKind::Truncated));
+        }
+        if self.buffer.len() != record.seq.len() {
+            return Err(self.error(FastqErrorKind::LengthMismatch {
+                seq: record.seq.len(),
+                qual: self.buffer.len(),
+            }));
+        }
+        record.qual.clear();
+        record.qual.extend_from_slice(&self.buffer);
+
+        self.record += 1;
+        Ok(true)
+    }
+
+    // Read the next line into the buffer without its line terminator.
+    // Returns `Ok(false)` at the end of the input.
+    fn next_line(&mut self) -> Result<bool, FastqError> {
+        self.buffer.clear();
+        // `offset` and `line` describe the line that is about to be read
+        self.offset += self.consumed;
+        self.line += 1;
+        let n = self
+            .read
+            .read_until(b'\n', &mut self.buffer)
+            .map_err(|e| self.error(FastqErrorKind::Io(e)))?;
+        self.consumed = n as u64;
+        if n == 0 {
+            return Ok(false);
+        }
+        self.terminated = self.buffer.last() == Some(&b'\n');
+        if self.terminated {
+            self.buffer.pop();
+            if self.buffer.last() == Some(&b'\r') {
+                self.buffer.pop();
+            }
+        }
+        Ok(true)
+    }
+
+    // Like `next_line`, but the end of the input is an error.
+    fn expect_line(&mut self) -> Result<(), FastqError> {
+        if self.next_line()? {
+            Ok(())
+        } else {
+            Err(self.error(FastqErrorKind::Truncated))
+        }
+    }
+
+    fn error(&self, kind: FastqErrorKind) -> FastqError {
+        FastqError {
+            kind,
+            record: self.record,
+            offset: self.offset,
+            line: self.line,
+        }
+    }
+}
+
+// Name part of a header, i.e. everything up to the first whitespace.
+fn header_name(header: &[u8]) -> &[u8] {
+    match header.iter().position(|b| b.is_ascii_whitespace()) {
+        Some(end) => &header[..end],
+        None => header,
+    }
+}
+
+// IUPAC nucleotide codes in either case, plus `.` and `-` for gaps/no-calls.
+fn is_valid_base(base: u8) -> bool {
+    base.is_ascii_alphabetic() || base == b'.' || base == b'-'
+}
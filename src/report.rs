@@ -0,0 +1,433 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Write};
+
+use gnuplot::{AxesCommon, Figure};
+use prettytable::{Cell, Row, Table as PrettyTable};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::SampleMetadata;
+
+/// A single value of a report: a count, a measurement or a label.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Value {
+    Int(u64),
+    Float(f64),
+    Text(String),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(v) => write!(f, "{}", v),
+            // Honour a requested precision, e.g. `{:.2}` for terminal output
+            Value::Float(v) => match f.precision() {
+                Some(precision) => write!(f, "{:.*}", precision, v),
+                None => write!(f, "{}", v),
+            },
+            Value::Text(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+impl From<u64> for Value {
+    fn from(v: u64) -> Self {
+        Value::Int(v)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(v: usize) -> Self {
+        Value::Int(v as u64)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Value::Float(v)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(v: f32) -> Self {
+        Value::Float(v as f64)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::Text(v.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::Text(v)
+    }
+}
+
+/// A named scalar result, e.g. the mean quality of all reads.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Metric {
+    pub name: String,
+    pub value: Value,
+}
+
+/// Tabular results with column headers, e.g. one row per read position.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Table {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl Table {
+    pub fn new(name: &str, columns: &[&str]) -> Self {
+        Table {
+            name: name.to_string(),
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn push_row(&mut self, row: Vec<Value>) {
+        debug_assert_eq!(
+            row.len(),
+            self.columns.len(),
+            "Row does not fit the columns"
+        );
+        self.rows.push(row);
+    }
+}
+
+/// Data points of a line plot.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Series {
+    pub name: String,
+    pub x_label: String,
+    pub y_label: String,
+    pub x: Vec<f64>,
+    pub y: Vec<f64>,
+}
+
+impl Series {
+    pub fn new(name: &str, x_label: &str, y_label: &str, x: Vec<f64>, y: Vec<f64>) -> Self {
+        Series {
+            name: name.to_string(),
+            x_label: x_label.to_string(),
+            y_label: y_label.to_string(),
+            x,
+            y,
+        }
+    }
+}
+
+/// The results of a statistic, shared by all output formats.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    pub name: String,
+    /// The group of reads the report is restricted to, e.g. a flowcell lane.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// The input files the report was computed from.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub samples: Vec<SampleMetadata>,
+    pub metrics: Vec<Metric>,
+    pub tables: Vec<Table>,
+    pub series: Vec<Series>,
+    /// Internal state of the statistic, e.g. raw counts. Only written to JSON.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<serde_json::Value>,
+}
+
+impl Report {
+    pub fn new(name: &str) -> Self {
+        Report {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Restrict the report to the reads of `group`.
+    pub fn with_group(mut self, group: &str) -> Self {
+        self.group = Some(group.to_string());
+        self
+    }
+
+    pub fn with_samples(mut self, samples: Vec<SampleMetadata>) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// The tables, preceded by a table of the input files if there are any.
+    fn all_tables(&self) -> Vec<Cow<'_, Table>> {
+        let mut tables = Vec::new();
+        if !self.samples.is_empty() {
+            let mut table = Table::new(
+                "Input files",
+                &[
+                    "File",
+                    "Sample",
+                    "Sample Number",
+                    "Barcode",
+                    "Lane",
+                    "Read",
+                    "Set",
+                ],
+            );
+            for sample in &self.samples {
+                let optional = |value: Option<usize>| value.map_or(Value::from("-"), Value::from);
+                let read = match (sample.read, sample.index_read) {
+                    (Some(read), true) => Value::from(format!("I{}", read)),
+                    (read, _) => optional(read),
+                };
+                table.push_row(vec![
+                    sample.file_name.as_str().into(),
+                    sample.sample_name.as_str().into(),
+                    optional(sample.sample_number),
+                    sample.barcode.as_deref().unwrap_or("-").into(),
+                    optional(sample.lane),
+                    read,
+                    optional(sample.set_number),
+                ]);
+            }
+            tables.push(Cow::Owned(table));
+        }
+        tables.extend(self.tables.iter().map(Cow::Borrowed));
+        tables
+    }
+
+    /// The name, followed by the group if there is one.
+    pub fn title(&self) -> String {
+        match &self.group {
+            Some(group) => format!("{} [{}]", self.name, group),
+            None => self.name.clone(),
+        }
+    }
+
+    pub fn with_metric(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.metrics.push(Metric {
+            name: name.to_string(),
+            value: value.into(),
+        });
+        self
+    }
+
+    pub fn with_table(mut self, table: Table) -> Self {
+        self.tables.push(table);
+        self
+    }
+
+    pub fn with_series(mut self, series: Series) -> Self {
+        self.series.push(series);
+        self
+    }
+}
+
+// ------------------- OUTPUT FORMATS -------------------
+
+pub fn write_json<W: Write>(mut out: W, reports: &[Report]) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut out, reports)?;
+    writeln!(out)
+}
+
+/// Tab-separated output, one block per metric list, table and series.
+pub fn write_tsv<W: Write>(mut out: W, reports: &[Report]) -> io::Result<()> {
+    for report in reports {
+        if !report.metrics.is_empty() {
+            writeln!(out, "# {}", report.title())?;
+            writeln!(out, "metric\tvalue")?;
+            for metric in &report.metrics {
+                writeln!(out, "{}\t{}", metric.name, metric.value)?;
+            }
+            writeln!(out)?;
+        }
+        for table in report.all_tables() {
+            writeln!(out, "# {}: {}", report.title(), table.name)?;
+            writeln!(out, "{}", table.columns.join("\t"))?;
+            for row in &table.rows {
+                let cells: Vec<String> = row.iter().map(|v| v.to_string()).collect();
+                writeln!(out, "{}", cells.join("\t"))?;
+            }
+            writeln!(out)?;
+        }
+        for series in &report.series {
+            writeln!(out, "# {}: {}", report.title(), series.name)?;
+            writeln!(out, "{}\t{}", series.x_label, series.y_label)?;
+            for (x, y) in series.x.iter().zip(&series.y) {
+                writeln!(out, "{}\t{}", x, y)?;
+            }
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Human readable tables for the terminal. Series are left out.
+pub fn write_text<W: Write>(mut out: W, reports: &[Report]) -> io::Result<()> {
+    for report in reports {
+        writeln!(out, "\n{}", report.title())?;
+        if !report.metrics.is_empty() {
+            let mut table = PrettyTable::new();
+            for metric in &report.metrics {
+                table.add_row(Row::new(vec![
+                    Cell::new(&metric.name),
+                    Cell::new(&format!("{:.2}", metric.value)).style_spec("r"),
+                ]));
+            }
+            table.print(&mut out)?;
+        }
+        for t in report.all_tables() {
+            writeln!(out, "{}:", t.name)?;
+            let mut table = PrettyTable::new();
+            table.set_titles(Row::new(t.columns.iter().map(|c| Cell::new(c)).collect()));
+            for row in &t.rows {
+                table.add_row(Row::new(
+                    row.iter()
+                        .map(|v| Cell::new(&format!("{:.2}", v)).style_spec("r"))
+                        .collect(),
+                ));
+            }
+            table.print(&mut out)?;
+        }
+    }
+    Ok(())
+}
+
+/// A self-contained HTML page with tables and SVG line plots.
+pub fn write_html<W: Write>(mut out: W, reports: &[Report]) -> io::Result<()> {
+    writeln!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>FastqScan report</title>"
+    )?;
+    writeln!(
+        out,
+        "<style>body{{font-family:sans-serif}}table{{border-collapse:collapse;margin-bottom:1em}}\
+         td,th{{border:1px solid #ccc;padding:2px 6px;text-align:right}}</style>\n</head>\n<body>"
+    )?;
+    writeln!(out, "<h1>FastqScan report</h1>")?;
+    for report in reports {
+        writeln!(out, "<h2>{}</h2>", escape(&report.title()))?;
+        if !report.metrics.is_empty() {
+            writeln!(out, "<table>")?;
+            for metric in &report.metrics {
+                writeln!(
+                    out,
+                    "<tr><th>{}</th><td>{}</td></tr>",
+                    escape(&metric.name),
+                    escape(&format!("{:.2}", metric.value))
+                )?;
+            }
+            writeln!(out, "</table>")?;
+        }
+        for series in &report.series {
+            writeln!(out, "<h3>{}</h3>", escape(&series.name))?;
+            write_svg(&mut out, series)?;
+        }
+        for table in report.all_tables() {
+            writeln!(out, "<h3>{}</h3>\n<table>\n<tr>", escape(&table.name))?;
+            for column in &table.columns {
+                write!(out, "<th>{}</th>", escape(column))?;
+            }
+            writeln!(out, "</tr>")?;
+            for row in &table.rows {
+                write!(out, "<tr>")?;
+                for value in row {
+                    write!(out, "<td>{}</td>", escape(&format!("{:.2}", value)))?;
+                }
+                writeln!(out, "</tr>")?;
+            }
+            writeln!(out, "</table>")?;
+        }
+    }
+    writeln!(out, "</body>\n</html>")
+}
+
+// Plot a series as an SVG polyline scaled to its data range.
+fn write_svg<W: Write>(out: &mut W, series: &Series) -> io::Result<()> {
+    const WIDTH: f64 = 640.0;
+    const HEIGHT: f64 = 320.0;
+    const MARGIN: f64 = 50.0;
+
+    let range = |values: &[f64]| {
+        let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        if min.is_finite() && max > min {
+            (min, max)
+        } else {
+            (min.min(0.0), min.max(0.0) + 1.0)
+        }
+    };
+    let (x_min, x_max) = range(&series.x);
+    let (y_min, y_max) = range(&series.y);
+    let scale_x = |x: f64| MARGIN + (x - x_min) / (x_max - x_min) * (WIDTH - 2.0 * MARGIN);
+    let scale_y =
+        |y: f64| HEIGHT - MARGIN - (y - y_min) / (y_max - y_min) * (HEIGHT - 2.0 * MARGIN);
+
+    let points: Vec<String> = series
+        .x
+        .iter()
+        .zip(&series.y)
+        .map(|(&x, &y)| format!("{:.1},{:.1}", scale_x(x), scale_y(y)))
+        .collect();
+
+    writeln!(
+        out,
+        "<svg width=\"{w}\" height=\"{h}\" xmlns=\"http://www.w3.org/2000/svg\">\n\
+         <rect x=\"{m}\" y=\"{m}\" width=\"{iw}\" height=\"{ih}\" fill=\"none\" stroke=\"#999\"/>\n\
+         <polyline fill=\"none\" stroke=\"blue\" points=\"{points}\"/>\n\
+         <text x=\"{m}\" y=\"{ty}\" font-size=\"11\">{x_min}</text>\n\
+         <text x=\"{xr}\" y=\"{ty}\" font-size=\"11\" text-anchor=\"end\">{x_max}</text>\n\
+         <text x=\"{cx}\" y=\"{lx}\" font-size=\"12\" text-anchor=\"middle\">{x_label}</text>\n\
+         <text x=\"{yl}\" y=\"{yb}\" font-size=\"11\" text-anchor=\"end\">{y_min:.2}</text>\n\
+         <text x=\"{yl}\" y=\"{yt}\" font-size=\"11\" text-anchor=\"end\">{y_max:.2}</text>\n\
+         <text x=\"12\" y=\"{cy}\" font-size=\"12\" text-anchor=\"middle\" \
+         transform=\"rotate(-90 12 {cy})\">{y_label}</text>\n</svg>",
+        w = WIDTH,
+        h = HEIGHT,
+        m = MARGIN,
+        iw = WIDTH - 2.0 * MARGIN,
+        ih = HEIGHT - 2.0 * MARGIN,
+        points = points.join(" "),
+        ty = HEIGHT - MARGIN + 14.0,
+        xr = WIDTH - MARGIN,
+        cx = WIDTH / 2.0,
+        lx = HEIGHT - 10.0,
+        yl = MARGIN - 4.0,
+        yb = HEIGHT - MARGIN,
+        yt = MARGIN + 10.0,
+        cy = HEIGHT / 2.0,
+        x_label = escape(&series.x_label),
+        y_label = escape(&series.y_label),
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Show every series in a gnuplot window.
+pub fn plot(reports: &[Report]) {
+    for report in reports {
+        for series in &report.series {
+            let mut fg = Figure::new();
+            fg.axes2d()
+                .set_title(&format!("{}: {}", report.title(), series.name), &[])
+                .set_x_label(&series.x_label, &[])
+                .set_y_label(&series.y_label, &[])
+                .lines(
+                    &series.x,
+                    &series.y,
+                    &[gnuplot::Caption(&series.name), gnuplot::Color("blue")],
+                );
+
+            if let Err(e) = fg.show() {
+                eprintln!("Could not plot {}: {}", series.name, e);
+            }
+        }
+    }
+}
@@ -1,13 +1,41 @@
-use std::io::{self, BufRead};
+use std::any::Any;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::sync::{Mutex, mpsc};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+
+use crate::encoding::QualityEncoding;
+use crate::error::FastqScanError;
+use crate::reader::FastqReader;
+use crate::report::Report;
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct FastqRecord {
+    /// Read identifier, i.e. the header up to the first whitespace (without `@`).
+    pub name: Vec<u8>,
+    /// Rest of the header after the identifier, may be empty.
+    pub comment: Vec<u8>,
     pub seq: Vec<u8>,
     pub qual: Vec<u8>,
 }
 
+impl FastqRecord {
+    /// The read identifier as text, invalid UTF-8 is replaced.
+    pub fn name_str(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.name)
+    }
+
+    /// The header comment as text, invalid UTF-8 is replaced.
+    pub fn comment_str(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.comment)
+    }
+}
+
 #[typetag::serde(tag = "type")]
-pub trait Statistic {
+pub trait Statistic: Any + Send {
     /* Statistics:
 
     * average base quality (Phred)
@@ -17,10 +45,249 @@ pub trait Statistic {
     */
 
     fn process(&mut self, record: &FastqRecord);
-    // TODO - find a way to represent the results.
-    // Let's try to identify the shared parts of *any* statistic
-    // and report these in some fashion.
-    // fn report(self) -> ?
+
+    /// The results in the representation shared by all output formats.
+    fn report(&self) -> Report;
+
+    /// All reports of the statistic. Most statistics have exactly one, statistics
+    /// that split the reads into groups add one per group.
+    fn reports(&self) -> Vec<Report> {
+        vec![self.report()]
+    }
+
+    /// Add the results of `other`, a statistic of the same type, to `self`.
+    ///
+    /// Merging the partial results of two parts of the input gives the same
+    /// result as processing the whole input, as long as the parts are merged
+    /// in the order they appear in the input.
+    fn merge(&mut self, other: &dyn Statistic);
+
+    /// A new statistic of the same type and configuration that has not seen any records.
+    fn empty(&self) -> Box<dyn Statistic>;
+
+    /// Whether the result of [`Statistic::merge`] depends on the order of the
+    /// parts, e.g. because the statistic keeps the first sequences it sees.
+    /// Other statistics can merge parts in any order.
+    fn ordered(&self) -> bool {
+        false
+    }
+}
+
+/// Downcast `other` to the type of the statistic it is merged into.
+///
+/// Panics if the types differ, merging unrelated statistics is a programming error.
+pub fn downcast<T: Statistic>(other: &dyn Statistic) -> &T {
+    let any: &dyn Any = other;
+    any.downcast_ref::<T>().unwrap_or_else(|| {
+        panic!(
+            "Cannot merge {} into {}",
+            other.typetag_name(),
+            std::any::type_name::<T>()
+        )
+    })
+}
+
+/// Totals of a successful run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunSummary {
+    pub records: u64,
+    pub bases: u64,
+    /// Quality encoding of the input, of the first input after merging.
+    pub encoding: Option<QualityEncoding>,
+}
+
+impl RunSummary {
+    pub fn merge(&mut self, other: &RunSummary) {
+        self.records += other.records;
+        self.bases += other.bases;
+        self.encoding = self.encoding.or(other.encoding);
+    }
+}
+
+// Number of records handed to a worker thread at a time. It must stay below the
+// limit of the sequence counters, whose merge is only exact for smaller parts.
+#[cfg(not(test))]
+const CHUNK_SIZE: usize = 16_384;
+// Small enough that the tests run through more chunks than are on their way at a time
+#[cfg(test)]
+const CHUNK_SIZE: usize = 256;
+
+pub(crate) fn merge_statistics(
+    statistics: &mut [Box<dyn Statistic>],
+    other: &[Box<dyn Statistic>],
+) {
+    assert_eq!(
+        statistics.len(),
+        other.len(),
+        "Cannot merge runners with different statistics"
+    );
+    for (statistic, other) in statistics.iter_mut().zip(other) {
+        statistic.merge(other.as_ref());
+    }
+}
+
+/// Results that can be computed on several threads, see [`process_chunks`].
+pub(crate) trait Partial: Send + Sized {
+    type Item: Send;
+
+    /// An empty copy with the same configuration.
+    fn empty(&self) -> Self;
+
+    fn add(&mut self, item: &Self::Item);
+
+    /// Move the results that must be merged in input order out into a new
+    /// value and leave empty ones behind, see [`Statistic::ordered`].
+    fn take_ordered(&mut self) -> Self;
+
+    /// Merge either the results of `other` that must be merged in input
+    /// order, or all other results.
+    fn merge_part(&mut self, other: &Self, ordered: bool);
+}
+
+// Merge the statistics of `other` whose `ordered()` equals `ordered`.
+fn merge_statistics_part(
+    statistics: &mut [Box<dyn Statistic>],
+    other: &[Box<dyn Statistic>],
+    ordered: bool,
+) {
+    assert_eq!(
+        statistics.len(),
+        other.len(),
+        "Cannot merge runners with different statistics"
+    );
+    for (statistic, other) in statistics.iter_mut().zip(other) {
+        if statistic.ordered() == ordered {
+            statistic.merge(other.as_ref());
+        }
+    }
+}
+
+impl Partial for Vec<Box<dyn Statistic>> {
+    type Item = FastqRecord;
+
+    fn empty(&self) -> Self {
+        self.iter().map(|s| s.empty()).collect()
+    }
+
+    fn add(&mut self, record: &FastqRecord) {
+        for statistic in self.iter_mut() {
+            statistic.process(record);
+        }
+    }
+
+    fn take_ordered(&mut self) -> Self {
+        self.iter_mut()
+            .map(|statistic| {
+                let empty = statistic.empty();
+                if statistic.ordered() {
+                    std::mem::replace(statistic, empty)
+                } else {
+                    empty
+                }
+            })
+            .collect()
+    }
+
+    fn merge_part(&mut self, other: &Self, ordered: bool) {
+        merge_statistics_part(self, other, ordered);
+    }
+}
+
+/// Processes the items produced by `next` on `threads` worker threads.
+///
+/// The items are handed out in chunks. Every worker adds its chunks to its
+/// own copy of `target`, which is merged into `target` once at the end. Only
+/// the results that depend on the input order are split off after every chunk
+/// and merged back in input order, so the result is identical to processing
+/// all items in a single pass. At most two chunks per worker are on their way
+/// at any time.
+pub(crate) fn process_chunks<S, E>(
+    target: &mut S,
+    threads: usize,
+    next: &mut (dyn FnMut() -> Result<Option<S::Item>, E> + Send),
+) -> Result<(), E>
+where
+    S: Partial,
+    E: Send,
+{
+    let depth = threads * 2;
+    let partials: Vec<S> = (0..threads).map(|_| target.empty()).collect();
+
+    let (chunk_tx, chunk_rx) = mpsc::sync_channel::<(usize, Vec<S::Item>)>(depth);
+    let chunk_rx = Mutex::new(chunk_rx);
+    let (result_tx, result_rx) = mpsc::channel();
+
+    // The reader takes a credit for every chunk, which is returned once the
+    // chunk has been merged, so neither the queue nor `pending` grows beyond `depth`
+    let (credit_tx, credit_rx) = mpsc::sync_channel(depth);
+    for _ in 0..depth {
+        credit_tx.send(()).expect("Credits fit into the channel");
+    }
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = partials
+            .into_iter()
+            .map(|mut partial| {
+                let chunk_rx = &chunk_rx;
+                let result_tx = result_tx.clone();
+                scope.spawn(move || {
+                    loop {
+                        // Release the lock before processing, so others can take the next chunk
+                        let message = chunk_rx.lock().unwrap().recv();
+                        let Ok((index, chunk)) = message else {
+                            break;
+                        };
+                        for item in &chunk {
+                            partial.add(item);
+                        }
+                        if result_tx.send((index, partial.take_ordered())).is_err() {
+                            break;
+                        }
+                    }
+                    partial
+                })
+            })
+            .collect();
+        drop(result_tx);
+
+        let reader = scope.spawn(move || {
+            for index in 0.. {
+                let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+                while chunk.len() < CHUNK_SIZE {
+                    match next()? {
+                        Some(item) => chunk.push(item),
+                        None => break,
+                    }
+                }
+                if chunk.is_empty()
+                    || credit_rx.recv().is_err()
+                    || chunk_tx.send((index, chunk)).is_err()
+                {
+                    break;
+                }
+            }
+            Ok(())
+        });
+
+        // Merge the order dependent results in input order as they come in
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        for (index, ordered) in result_rx {
+            pending.insert(index, ordered);
+            while let Some(ordered) = pending.remove(&next) {
+                target.merge_part(&ordered, true);
+                next += 1;
+                // Fails only once the reader is done
+                let _ = credit_tx.send(());
+            }
+        }
+
+        for worker in workers {
+            let partial = worker.join().expect("Worker thread panicked");
+            target.merge_part(&partial, false);
+        }
+        reader.join().expect("Reader thread panicked")
+    })
 }
 
 pub struct WorkflowRunner {
@@ -30,56 +297,94 @@ pub struct WorkflowRunner {
 impl WorkflowRunner {
     /// Process the FASTQ file.
     ///
-    /// Can return an I/O error or other errors (not in the signature at this point)
-    pub fn process<R>(&mut self, mut read: R)
+    /// Stops at the first malformed record, I/O or decompression error and
+    /// returns an error describing where it was found. A clean end of input
+    /// returns a summary of what was processed.
+    pub fn process<R>(&mut self, read: R) -> Result<RunSummary, FastqScanError>
+    where
+        R: BufRead,
+    {
+        self.process_records(FastqReader::new(read))
+    }
+
+    /// Process the records of `reader`, e.g. one with a given quality encoding,
+    /// see [`WorkflowRunner::process`].
+    pub fn process_records<R>(
+        &mut self,
+        mut reader: FastqReader<R>,
+    ) -> Result<RunSummary, FastqScanError>
     where
         R: BufRead,
     {
         let mut record = FastqRecord::default();
+        let mut summary = RunSummary::default();
 
-        while let Ok(()) = WorkflowRunner::parse_record(&mut read, &mut record) {
+        while reader.read_record(&mut record)? {
+            summary.records += 1;
+            summary.bases += record.seq.len() as u64;
             for statistic in self.statistics.iter_mut() {
                 statistic.process(&record);
             }
         }
 
-        //for statistic in self.statistics.iter() {
-        //    statistic.out(writer: );
-        //}
+        summary.encoding = reader.encoding();
+        Ok(summary)
     }
 
-    // Read data for a complete FASTQ record from `read`.
-    pub fn parse_record<R>(read: &mut R, record: &mut FastqRecord) -> io::Result<()>
+    /// Process the FASTQ file on `threads` worker threads.
+    ///
+    /// A reader thread parses the input into chunks of records, which are
+    /// processed by workers with their own empty copies of the statistics. The
+    /// result is identical to [`WorkflowRunner::process`], see [`process_chunks`].
+    pub fn process_parallel<R>(
+        &mut self,
+        read: R,
+        threads: usize,
+    ) -> Result<RunSummary, FastqScanError>
     where
-        R: BufRead,
+        R: BufRead + Send,
     {
-        let mut buffer = Vec::new();
+        self.process_records_parallel(FastqReader::new(read), threads)
+    }
 
-        if read.fill_buf()?.is_empty() {
-            return Err(io::Error::new(
-                io::ErrorKind::UnexpectedEof,
-                "FASTQ file is empty or improperly formatted",
-            ));
+    /// Process the records of `reader` on `threads` worker threads, see
+    /// [`WorkflowRunner::process_parallel`].
+    pub fn process_records_parallel<R>(
+        &mut self,
+        mut reader: FastqReader<R>,
+        threads: usize,
+    ) -> Result<RunSummary, FastqScanError>
+    where
+        R: BufRead + Send,
+    {
+        if threads <= 1 {
+            return self.process_records(reader);
         }
 
-        // Skip header line (line 1)
-        read.read_until(b'\n', &mut buffer)?;
-        buffer.clear();
-
-        // Sequence line (line 2)
-        read.read_until(b'\n', &mut buffer)?;
-        record.seq = buffer.strip_suffix(b"\n").unwrap_or(&buffer).to_vec();
-        buffer.clear();
-
-        // Skip '+' line (line 3)
-        read.read_until(b'\n', &mut buffer)?;
-        buffer.clear();
+        let mut summary = RunSummary::default();
+        let mut next = || -> Result<Option<FastqRecord>, FastqScanError> {
+            let mut record = FastqRecord::default();
+            if !reader.read_record(&mut record)? {
+                return Ok(None);
+            }
+            summary.records += 1;
+            summary.bases += record.seq.len() as u64;
+            Ok(Some(record))
+        };
+        process_chunks(&mut self.statistics, threads, &mut next)?;
+        summary.encoding = reader.encoding();
+        Ok(summary)
+    }
 
-        // Quality line (line 4)
-        read.read_until(b'\n', &mut buffer)?;
-        record.qual = buffer.strip_suffix(b"\n").unwrap_or(&buffer).to_vec();
+    /// Merge the statistics of `other`, which must hold the same kinds of
+    /// statistics in the same order, into this runner.
+    pub fn merge(&mut self, other: &WorkflowRunner) {
+        merge_statistics(&mut self.statistics, &other.statistics);
+    }
 
-        Ok(())
+    /// Empty copies of the statistics of this runner.
+    pub fn empty_statistics(&self) -> Vec<Box<dyn Statistic>> {
+        self.statistics.iter().map(|s| s.empty()).collect()
     }
 
     pub fn finalize(self) -> Vec<Box<dyn Statistic>> {
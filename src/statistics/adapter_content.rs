@@ -0,0 +1,204 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::report::{Report, Series, Table};
+use crate::runner::{FastqRecord, Statistic, downcast};
+
+// Adapters searched for by default, the first 12 bases as in FastQC.
+const BUILTIN: [(&str, &str); 7] = [
+    ("Illumina Universal Adapter", "AGATCGGAAGAG"),
+    ("Illumina Small RNA 3' Adapter", "TGGAATTCTCGG"),
+    ("Illumina Small RNA 5' Adapter", "GATCGTCGGACT"),
+    ("Nextera Transposase Sequence", "CTGTCTCTTATA"),
+    ("SOLiD Small RNA Adapter", "CGCCTTGGCCGT"),
+    ("PolyA", "AAAAAAAAAAAA"),
+    ("PolyG", "GGGGGGGGGGGG"),
+];
+
+/// An adapter sequence searched for in the reads.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Adapter {
+    pub name: String,
+    pub seq: Vec<u8>,
+}
+
+impl Adapter {
+    pub fn new(name: &str, seq: &[u8]) -> Self {
+        Adapter {
+            name: name.to_string(),
+            seq: seq.to_ascii_uppercase(),
+        }
+    }
+
+    /// An adapter read from user input, e.g. a FASTA file. The sequence must
+    /// consist of `ACGTN` only, in either case.
+    pub fn parse(name: &str, seq: &[u8]) -> Result<Self, String> {
+        if name.trim().is_empty() {
+            return Err("the adapter name is empty".to_string());
+        }
+        if seq.is_empty() || !seq.iter().all(|b| b"ACGTNacgtn".contains(b)) {
+            return Err(format!(
+                "'{}' is not a nucleotide sequence",
+                String::from_utf8_lossy(seq)
+            ));
+        }
+        Ok(Adapter::new(name.trim(), seq))
+    }
+
+    /// The built-in list of adapters.
+    pub fn builtin() -> Vec<Adapter> {
+        BUILTIN
+            .iter()
+            .map(|(name, seq)| Adapter::new(name, seq.as_bytes()))
+            .collect()
+    }
+}
+
+/// Parses `NAME=SEQUENCE`, as given on the command line.
+impl FromStr for Adapter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, seq) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected NAME=SEQUENCE, found '{}'", s))?;
+        Adapter::parse(name, seq.as_bytes())
+    }
+}
+
+/// Cumulative percentage of reads in which an adapter has started at or
+/// before each position, as in FastQC.
+///
+/// Only the first occurrence of an adapter in a read is counted.
+#[derive(Serialize, Deserialize)]
+pub struct AdapterContent {
+    adapters: Vec<Adapter>,
+    // starts[adapter][pos]: reads in which the adapter first occurs at this position
+    starts: Vec<Vec<u64>>,
+    reads: u64,
+    // Length of the longest read, the positions reported
+    max_len: usize,
+}
+
+impl Default for AdapterContent {
+    fn default() -> Self {
+        AdapterContent::new()
+    }
+}
+
+impl AdapterContent {
+    pub fn new() -> Self {
+        AdapterContent::with_adapters(Adapter::builtin())
+    }
+
+    /// Search for `adapters` only.
+    pub fn with_adapters(adapters: Vec<Adapter>) -> Self {
+        AdapterContent {
+            starts: vec![Vec::new(); adapters.len()],
+            adapters,
+            reads: 0,
+            max_len: 0,
+        }
+    }
+
+    pub fn adapters(&self) -> &[Adapter] {
+        &self.adapters
+    }
+
+    /// Cumulative percentage of reads containing each adapter, for every position.
+    pub fn percentages(&self) -> Vec<Vec<f64>> {
+        self.starts
+            .iter()
+            .map(|starts| {
+                let mut seen = 0;
+                (0..self.max_len)
+                    .map(|pos| {
+                        seen += starts.get(pos).copied().unwrap_or(0);
+                        seen as f64 / self.reads as f64 * 100.0
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[typetag::serde]
+impl Statistic for AdapterContent {
+    fn process(&mut self, record: &FastqRecord) {
+        self.reads += 1;
+        self.max_len = self.max_len.max(record.seq.len());
+        for (adapter, starts) in self.adapters.iter().zip(self.starts.iter_mut()) {
+            let len = adapter.seq.len();
+            if len == 0 {
+                continue;
+            }
+            // Adapters are upper case, reads may not be
+            if let Some(pos) = record
+                .seq
+                .windows(len)
+                .position(|w| w.eq_ignore_ascii_case(&adapter.seq))
+            {
+                if starts.len() <= pos {
+                    starts.resize(pos + 1, 0);
+                }
+                starts[pos] += 1;
+            }
+        }
+    }
+
+    fn report(&self) -> Report {
+        let percentages = self.percentages();
+
+        let mut columns = vec!["Pos"];
+        columns.extend(self.adapters.iter().map(|a| a.name.as_str()));
+        let mut table = Table::new("Adapter content per position", &columns);
+        for pos in 0..self.max_len {
+            let mut row = vec![(pos + 1).into()];
+            row.extend(percentages.iter().map(|p| p[pos].into()));
+            table.push_row(row);
+        }
+
+        let mut report = Report::new("AdapterContent").with_metric("Reads", self.reads);
+        for (adapter, percentages) in self.adapters.iter().zip(&percentages) {
+            if let Some(&last) = percentages.last() {
+                report = report.with_metric(&format!("{} (%)", adapter.name), last);
+            }
+        }
+        report = report.with_table(table);
+
+        let positions: Vec<f64> = (1..=self.max_len).map(|p| p as f64).collect(); // X-axis: positions
+        for (adapter, percentages) in self.adapters.iter().zip(percentages) {
+            report = report.with_series(Series::new(
+                &adapter.name,
+                "Position",
+                "% Reads with Adapter",
+                positions.clone(),
+                percentages,
+            ));
+        }
+        report
+    }
+
+    fn empty(&self) -> Box<dyn Statistic> {
+        Box::new(AdapterContent::with_adapters(self.adapters.clone()))
+    }
+
+    fn merge(&mut self, other: &dyn Statistic) {
+        let other = downcast::<Self>(other);
+        assert_eq!(
+            self.adapters, other.adapters,
+            "Cannot merge adapter content of different adapters"
+        );
+        for (starts, other) in self.starts.iter_mut().zip(&other.starts) {
+            if starts.len() < other.len() {
+                starts.resize(other.len(), 0);
+            }
+            for (count, &n) in starts.iter_mut().zip(other) {
+                *count += n;
+            }
+        }
+        self.reads += other.reads;
+        self.max_len = self.max_len.max(other.max_len);
+    }
+}
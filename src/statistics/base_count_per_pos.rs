@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 
-use crate::runner::{FastqRecord, Statistic};
+use crate::report::{Report, Series, Table};
+use crate::runner::{FastqRecord, Statistic, downcast};
 
 #[derive(Default, Serialize, Deserialize)]
 pub struct BaseCountPerPos {
@@ -22,41 +23,6 @@ impl BaseCountPerPos {
             self.nuc_counts.resize(length, CountNucleotides::new());
         }
     }
-
-    fn _out(&self) {
-        let mut tab_gc = Vec::new();
-        tab_gc.resize(self.nuc_counts.len(), 0.0);
-
-        for (iter, read) in self.nuc_counts.iter().enumerate() {
-            read._get_percentage();
-            tab_gc[iter] = self.nuc_counts[iter]._get_gc_percentage();
-        }
-
-        println!("\nNucleotide Composition:");
-        println!(
-            "{:<6} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
-            "Pos", "A%", "C%", "G%", "T%", "N%", "GC%"
-        );
-
-        for (i, counts) in self.nuc_counts.iter().enumerate() {
-            let (a, c, g, t, n) = counts._get_percentage();
-            let gc_percent = if i < tab_gc.len() {
-                tab_gc[i]
-            } else {
-                0.0 // Default value if tab_gc hasn't been computed for this position
-            };
-            println!(
-                "{:<6} {:>7.2}% {:>7.2}% {:>7.2}% {:>7.2}% {:>7.2}% {:>7.2}%",
-                i + 1,
-                a * 100.0,
-                c * 100.0,
-                g * 100.0,
-                t * 100.0,
-                n * 100.0,
-                gc_percent
-            );
-        }
-    }
 }
 
 #[typetag::serde]
@@ -69,6 +35,67 @@ impl Statistic for BaseCountPerPos {
             self.nuc_counts[index].add_base(*base);
         }
     }
+
+    fn report(&self) -> Report {
+        let mut table = Table::new(
+            "Nucleotide composition",
+            &["Pos", "A%", "C%", "G%", "T%", "N%", "GC%"],
+        );
+        for (i, counts) in self.nuc_counts.iter().enumerate() {
+            let (a, c, g, t, n) = counts.get_percentage();
+            table.push_row(vec![
+                (i + 1).into(),
+                (a * 100.0).into(),
+                (c * 100.0).into(),
+                (g * 100.0).into(),
+                (t * 100.0).into(),
+                (n * 100.0).into(),
+                counts.get_gc_percentage().into(),
+            ]);
+        }
+
+        let positions = (1..=self.nuc_counts.len()).map(|p| p as f64).collect();
+        let gc = self
+            .nuc_counts
+            .iter()
+            .map(|c| c.get_gc_percentage())
+            .collect();
+
+        // Composition over all positions
+        let mut total = CountNucleotides::new();
+        for counts in &self.nuc_counts {
+            total.merge(counts);
+        }
+        let (a, c, g, t, n) = total.get_percentage();
+
+        Report::new("BaseCountPerPos")
+            .with_metric("A%", a * 100.0)
+            .with_metric("C%", c * 100.0)
+            .with_metric("G%", g * 100.0)
+            .with_metric("T%", t * 100.0)
+            .with_metric("N%", n * 100.0)
+            .with_metric("GC%", total.get_gc_percentage())
+            .with_table(table)
+            .with_series(Series::new(
+                "GC content per position",
+                "Position",
+                "GC%",
+                positions,
+                gc,
+            ))
+    }
+
+    fn empty(&self) -> Box<dyn Statistic> {
+        Box::new(BaseCountPerPos::new())
+    }
+
+    fn merge(&mut self, other: &dyn Statistic) {
+        let other = downcast::<Self>(other);
+        self.ensure_length(other.nuc_counts.len());
+        for (counts, other) in self.nuc_counts.iter_mut().zip(&other.nuc_counts) {
+            counts.merge(other);
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -101,7 +128,15 @@ impl CountNucleotides {
         }
     }
 
-    pub fn _get_percentage(&self) -> (f64, f64, f64, f64, f64) {
+    pub fn merge(&mut self, other: &CountNucleotides) {
+        self.a += other.a;
+        self.c += other.c;
+        self.g += other.g;
+        self.t += other.t;
+        self.n += other.n;
+    }
+
+    pub fn get_percentage(&self) -> (f64, f64, f64, f64, f64) {
         let total = self.a + self.c + self.g + self.t + self.n;
         if total == 0 {
             return (0.0, 0.0, 0.0, 0.0, 0.0);
@@ -115,11 +150,11 @@ impl CountNucleotides {
         )
     }
 
-    pub fn _get_gc_percentage(&self) -> f32 {
+    pub fn get_gc_percentage(&self) -> f64 {
         let total = self.a + self.c + self.g + self.t + self.n;
         if total == 0 {
             return 0.0;
         }
-        ((self.g + self.c) as f32 / total as f32) * 100.0
+        ((self.g + self.c) as f64 / total as f64) * 100.0
     }
 }
@@ -1,30 +1,26 @@
 use serde::{Deserialize, Serialize};
 
-use crate::runner::{FastqRecord, Statistic};
+use crate::report::Report;
+use crate::runner::{FastqRecord, Statistic, downcast};
+use crate::statistics::histogram::Histogram;
 
-#[derive(Default, Serialize, Deserialize)]
+/// Distribution of the GC content of the reads, in bins of 1%.
+#[derive(Serialize, Deserialize)]
 pub struct BaseCountRead {
-    pub gc: Vec<f32>,
+    gc: Histogram,
 }
 
-impl BaseCountRead {
-    pub fn new() -> Self {
-        BaseCountRead { gc: Vec::new() }
+impl Default for BaseCountRead {
+    fn default() -> Self {
+        BaseCountRead::new()
     }
+}
 
-    fn _out(&self) {
-        // Calculate and display summary statistics
-        if !self.gc.is_empty() {
-            let min = self.gc.iter().fold(f32::INFINITY, |a, &b| a.min(b));
-            let max = self.gc.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b));
-            let sum: f32 = self.gc.iter().sum();
-            let mean = sum / self.gc.len() as f32;
-
-            println!("\nSummary Statistics:");
-            println!("{:<15} {:>8.2}%", "Minimum GC%", min);
-            println!("{:<15} {:>8.2}%", "Maximum GC%", max);
-            println!("{:<15} {:>8.2}%", "Average GC%", mean);
-            println!("{:<15} {:>8}", "Total Reads", self.gc.len());
+impl BaseCountRead {
+    pub fn new() -> Self {
+        // 100% gets a bin of its own
+        BaseCountRead {
+            gc: Histogram::new(0.0, 1.0, 101),
         }
     }
 }
@@ -33,16 +29,32 @@ impl BaseCountRead {
 impl Statistic for BaseCountRead {
     // Processes a FastqRecord to calculate GC content.
     fn process(&mut self, record: &FastqRecord) {
-        let mut gc_count = 0.0;
         let len = record.seq.len();
-        // Iterate over the sequence and count G and C bases
-        for base in record.seq.iter() {
-            match base {
-                b'C' => gc_count += 1.0,
-                b'G' => gc_count += 1.0,
-                _ => gc_count += 0.0,
-            }
+        if len == 0 {
+            return;
         }
-        self.gc.push(gc_count / len as f32 * 100.0);
+        // Count G and C bases
+        let gc_count = record
+            .seq
+            .iter()
+            .filter(|&&base| base == b'C' || base == b'G')
+            .count();
+        self.gc.add(gc_count as f64 / len as f64 * 100.0);
+    }
+
+    fn report(&self) -> Report {
+        self.gc.report_into(
+            Report::new("BaseCountRead"),
+            "GC%",
+            "GC content distribution",
+        )
+    }
+
+    fn empty(&self) -> Box<dyn Statistic> {
+        Box::new(BaseCountRead::new())
+    }
+
+    fn merge(&mut self, other: &dyn Statistic) {
+        self.gc.merge(&downcast::<Self>(other).gc);
     }
 }
@@ -0,0 +1,167 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::header::{HeaderFormat, ReadHeader};
+use crate::report::{Report, Table};
+use crate::runner::{FastqRecord, Statistic, downcast};
+
+/// Flowcell and lane a read was sequenced on.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct LaneKey {
+    pub flowcell_id: String,
+    pub lane: usize,
+}
+
+impl LaneKey {
+    /// The lane from a read header, `None` if it has no lane information.
+    pub fn from_header(header: ReadHeader) -> Option<Self> {
+        Some(LaneKey {
+            // Pre Casava 1.8 headers only name the instrument
+            flowcell_id: header.flowcell_id.or(header.instrument)?,
+            lane: header.lane?,
+        })
+    }
+}
+
+impl fmt::Display for LaneKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} lane {}", self.flowcell_id, self.lane)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Group {
+    // `None` for reads without lane information
+    key: Option<LaneKey>,
+    reads: u64,
+    statistics: Vec<Box<dyn Statistic>>,
+}
+
+impl Group {
+    fn label(&self) -> String {
+        match &self.key {
+            Some(key) => key.to_string(),
+            None => "unknown lane".to_string(),
+        }
+    }
+}
+
+/// Computes a set of statistics separately for every `(flowcell_id, lane)`.
+///
+/// The flowcell and lane are read from the header of each read, reads without
+/// them form a group of their own. Besides an overview of the groups, every
+/// statistic is reported once per group.
+#[derive(Serialize, Deserialize)]
+pub struct ByLane {
+    // Empty statistics that every new group starts with
+    #[serde(skip)]
+    template: Vec<Box<dyn Statistic>>,
+    // Sorted by key, reads without lane information first
+    groups: Vec<Group>,
+    // Format of the read headers, detected from the first read
+    #[serde(skip)]
+    format: Option<HeaderFormat>,
+}
+
+impl ByLane {
+    /// Compute empty copies of `template` for every lane.
+    pub fn new(template: Vec<Box<dyn Statistic>>) -> Self {
+        ByLane {
+            template,
+            groups: Vec::new(),
+            format: None,
+        }
+    }
+
+    // The group for `key`, created if it does not exist yet.
+    fn group(&mut self, key: Option<LaneKey>) -> &mut Group {
+        let index = match self.groups.binary_search_by(|g| g.key.cmp(&key)) {
+            Ok(index) => index,
+            Err(index) => {
+                let group = Group {
+                    key,
+                    reads: 0,
+                    statistics: self.template.iter().map(|s| s.empty()).collect(),
+                };
+                self.groups.insert(index, group);
+                index
+            }
+        };
+        &mut self.groups[index]
+    }
+
+    /// The groups with the number of reads in each, in key order.
+    pub fn groups(&self) -> Vec<(Option<&LaneKey>, u64)> {
+        self.groups
+            .iter()
+            .map(|g| (g.key.as_ref(), g.reads))
+            .collect()
+    }
+}
+
+#[typetag::serde]
+impl Statistic for ByLane {
+    fn process(&mut self, record: &FastqRecord) {
+        let header = ReadHeader::parse_detected(record, &mut self.format);
+        let group = self.group(header.and_then(LaneKey::from_header));
+        group.reads += 1;
+        for statistic in group.statistics.iter_mut() {
+            statistic.process(record);
+        }
+    }
+
+    fn report(&self) -> Report {
+        let mut table = Table::new("Reads per lane", &["Flowcell", "Lane", "Reads"]);
+        for group in &self.groups {
+            table.push_row(match &group.key {
+                Some(key) => vec![
+                    key.flowcell_id.as_str().into(),
+                    key.lane.into(),
+                    group.reads.into(),
+                ],
+                None => vec!["unknown".into(), "unknown".into(), group.reads.into()],
+            });
+        }
+
+        Report::new("ByLane")
+            .with_metric("Lanes", self.groups.len())
+            .with_table(table)
+    }
+
+    fn reports(&self) -> Vec<Report> {
+        let mut reports = vec![self.report()];
+        for group in &self.groups {
+            let label = group.label();
+            reports.extend(
+                group
+                    .statistics
+                    .iter()
+                    .flat_map(|s| s.reports())
+                    .map(|r| r.with_group(&label)),
+            );
+        }
+        reports
+    }
+
+    fn empty(&self) -> Box<dyn Statistic> {
+        Box::new(ByLane::new(
+            self.template.iter().map(|s| s.empty()).collect(),
+        ))
+    }
+
+    fn ordered(&self) -> bool {
+        self.template.iter().any(|s| s.ordered())
+    }
+
+    fn merge(&mut self, other: &dyn Statistic) {
+        let other = downcast::<Self>(other);
+        for other in &other.groups {
+            let group = self.group(other.key.clone());
+            group.reads += other.reads;
+            for (statistic, other) in group.statistics.iter_mut().zip(&other.statistics) {
+                statistic.merge(other.as_ref());
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use crate::report::{Report, Series, Table};
+use crate::runner::{FastqRecord, Statistic, downcast};
+use crate::statistics::sequence_counter::SequenceCounter;
+use serde::{Deserialize, Serialize};
+
+/// Number of distinct sequences tracked by default.
+pub const DEFAULT_UNIQUE_LIMIT: usize = 100_000;
+
+// Lower bounds and labels of the duplication level bins.
+const LEVELS: [(u64, &str); 16] = [
+    (1, "1"),
+    (2, "2"),
+    (3, "3"),
+    (4, "4"),
+    (5, "5"),
+    (6, "6"),
+    (7, "7"),
+    (8, "8"),
+    (9, "9"),
+    (10, "10+"),
+    (50, "50+"),
+    (100, "100+"),
+    (500, "500+"),
+    (1_000, "1k+"),
+    (5_000, "5k+"),
+    (10_000, "10k+"),
+];
+
+/// Sequence duplication levels, as in FastQC.
+///
+/// Counts the first `limit` distinct sequences with a [`SequenceCounter`] and
+/// extrapolates the duplication levels to all reads.
+#[derive(Serialize, Deserialize)]
+pub struct Duplication {
+    counter: SequenceCounter,
+}
+
+impl Default for Duplication {
+    fn default() -> Self {
+        Duplication::new()
+    }
+}
+
+impl Duplication {
+    pub fn new() -> Self {
+        Duplication::with_limit(DEFAULT_UNIQUE_LIMIT)
+    }
+
+    /// Track at most `limit` distinct sequences.
+    pub fn with_limit(limit: usize) -> Self {
+        Duplication {
+            counter: SequenceCounter::new(limit),
+        }
+    }
+
+    // Estimate how many sequences with duplication `level` there are in all reads,
+    // given `observations` of them among the tracked ones.
+    fn corrected_count(&self, level: u64, observations: u64) -> f64 {
+        let (total, at_limit) = (self.counter.total(), self.counter.count_at_limit());
+        if at_limit == total || total - observations < at_limit {
+            return observations as f64;
+        }
+
+        // Probability that a sequence with this level was never seen before the limit
+        let mut p_not_seeing = 1.0;
+        let limit_of_caring = 1.0 - observations as f64 / (observations as f64 + 0.01);
+        for i in 0..at_limit {
+            let remaining = (total - i) as f64;
+            p_not_seeing *= (remaining - level as f64) / remaining;
+            if p_not_seeing < limit_of_caring {
+                p_not_seeing = 0.0;
+                break;
+            }
+        }
+        observations as f64 / (1.0 - p_not_seeing)
+    }
+
+    /// Percentage of the deduplicated and of all reads for every level bin,
+    /// and the percentage of reads that would remain after deduplication.
+    pub fn levels(&self) -> (Vec<f64>, Vec<f64>, f64) {
+        let mut dedup = vec![0.0; LEVELS.len()];
+        let mut total = vec![0.0; LEVELS.len()];
+        for (level, observations) in self.counter.level_counts() {
+            let count = self.corrected_count(level, observations);
+            let bin = LEVELS
+                .iter()
+                .rposition(|&(lower, _)| level >= lower)
+                .unwrap_or(0);
+            dedup[bin] += count;
+            total[bin] += count * level as f64;
+        }
+
+        let dedup_sum: f64 = dedup.iter().sum();
+        let total_sum: f64 = total.iter().sum();
+        if dedup_sum == 0.0 {
+            return (dedup, total, 100.0);
+        }
+        let percentages =
+            |values: Vec<f64>, sum: f64| values.iter().map(|v| v / sum * 100.0).collect();
+        (
+            percentages(dedup, dedup_sum),
+            percentages(total, total_sum),
+            dedup_sum / total_sum * 100.0,
+        )
+    }
+}
+
+#[typetag::serde]
+impl Statistic for Duplication {
+    fn process(&mut self, record: &FastqRecord) {
+        self.counter.add(&record.seq);
+    }
+
+    fn report(&self) -> Report {
+        let (dedup, total, remaining) = self.levels();
+
+        let mut table = Table::new(
+            "Sequence duplication levels",
+            &["Level", "% Deduplicated", "% Total"],
+        );
+        for (i, &(_, label)) in LEVELS.iter().enumerate() {
+            table.push_row(vec![label.into(), dedup[i].into(), total[i].into()]);
+        }
+
+        let bins = (1..=LEVELS.len()).map(|b| b as f64).collect(); // X-axis: level bins
+        Report::new("Duplication")
+            .with_metric("Reads", self.counter.total())
+            .with_metric("Tracked sequences", self.counter.len())
+            .with_metric("Remaining after deduplication (%)", remaining)
+            .with_table(table)
+            .with_series(Series::new(
+                "Sequence Duplication Levels",
+                "Duplication Level Bin",
+                "% of Total Reads",
+                bins,
+                total,
+            ))
+    }
+
+    fn empty(&self) -> Box<dyn Statistic> {
+        Box::new(Duplication::with_limit(self.counter.limit()))
+    }
+
+    // The counter keeps the first sequences of the input
+    fn ordered(&self) -> bool {
+        true
+    }
+
+    fn merge(&mut self, other: &dyn Statistic) {
+        self.counter.merge(&downcast::<Self>(other).counter);
+    }
+}
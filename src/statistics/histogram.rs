@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+
+use crate::report::{Report, Series, Table};
+
+// Fixed-point scale for the exact sum of all values.
+const SCALE: f64 = 1_000_000.0;
+
+/// Histogram of non-negative values with fixed-width bins, or bins of fixed
+/// width on a log scale for values that span several orders of magnitude.
+///
+/// Memory does not grow with the number of values. Values outside of the
+/// bins are counted in the first or last bin. Two histograms with the same
+/// bins merge exactly, independent of the order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    lower: f64,
+    // Width in decades for a log scale
+    bin_width: f64,
+    #[serde(default)]
+    log: bool,
+    counts: Vec<u64>,
+    min: f64,
+    max: f64,
+    // Sum of all values as fixed-point integer, so that merging is exact
+    sum: u64,
+}
+
+impl Histogram {
+    /// Histogram with `bins` bins of `bin_width` starting at `lower`.
+    pub fn new(lower: f64, bin_width: f64, bins: usize) -> Self {
+        Histogram {
+            lower,
+            bin_width,
+            log: false,
+            counts: vec![0; bins],
+            min: 0.0,
+            max: 0.0,
+            sum: 0,
+        }
+    }
+
+    /// Histogram with `bins_per_decade` bins of equal width on a log scale for
+    /// each of `decades` decades starting at `lower` (> 0). The quantiles are
+    /// then accurate to about `1.2 / bins_per_decade` of the value.
+    pub fn log(lower: f64, bins_per_decade: usize, decades: usize) -> Self {
+        Histogram {
+            log: true,
+            ..Histogram::new(
+                lower,
+                1.0 / bins_per_decade as f64,
+                bins_per_decade * decades,
+            )
+        }
+    }
+
+    pub fn add(&mut self, value: f64) {
+        if self.total() == 0 {
+            (self.min, self.max) = (value, value);
+        }
+        let offset = if self.log {
+            // Values up to 0 end up in the first bin, as the logarithm is -inf
+            (value / self.lower).log10()
+        } else {
+            value - self.lower
+        };
+        let bin = (offset / self.bin_width).floor().max(0.0) as usize;
+        let last = self.counts.len() - 1;
+        self.counts[bin.min(last)] += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += (value.max(0.0) * SCALE).round() as u64;
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        assert_eq!(
+            (self.lower, self.bin_width, self.log, self.counts.len()),
+            (other.lower, other.bin_width, other.log, other.counts.len()),
+            "Cannot merge histograms with different bins"
+        );
+        if other.total() == 0 {
+            return;
+        }
+        if self.total() == 0 {
+            (self.min, self.max) = (other.min, other.max);
+        }
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+    }
+
+    // Lower edge of a bin, rounded to hide floating point noise like 0.30000000000000004
+    fn edge(&self, bin: usize) -> f64 {
+        let edge = if self.log {
+            self.lower * 10f64.powf(bin as f64 * self.bin_width)
+        } else {
+            self.lower + bin as f64 * self.bin_width
+        };
+        (edge * SCALE).round() / SCALE
+    }
+
+    // Middle of a bin, on a log scale the geometric mean of the edges.
+    fn center(&self, bin: usize) -> f64 {
+        if self.log {
+            (self.edge(bin) * self.edge(bin + 1)).sqrt()
+        } else {
+            self.edge(bin) + self.bin_width / 2.0
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        (self.total() > 0).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        (self.total() > 0).then_some(self.max)
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        let total = self.total();
+        (total > 0).then(|| self.sum as f64 / SCALE / total as f64)
+    }
+
+    /// The `q`-quantile (0.0..=1.0) as the center of the bin it falls into,
+    /// limited to the observed range.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        let total = self.total();
+        if total == 0 {
+            return None;
+        }
+        let rank = ((q * total as f64).ceil() as u64).clamp(1, total);
+        let mut seen = 0;
+        for (bin, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Some(self.center(bin).clamp(self.min, self.max));
+            }
+        }
+        None
+    }
+
+    pub fn median(&self) -> Option<f64> {
+        self.quantile(0.5)
+    }
+
+    /// Add the summary metrics, the bins and a plot series to `report`.
+    /// Metric names end in `name`, e.g. "Mean quality".
+    pub fn report_into(&self, report: Report, name: &str, title: &str) -> Report {
+        let mut report = report.with_metric("Reads", self.total());
+        if let (Some(min), Some(max), Some(mean)) = (self.min(), self.max(), self.mean()) {
+            report = report
+                .with_metric(&format!("Mean {}", name), mean)
+                .with_metric(&format!("Min {}", name), min)
+                .with_metric(&format!("Max {}", name), max);
+            for (label, q) in [("Q1", 0.25), ("Median", 0.5), ("Q3", 0.75)] {
+                report = report.with_metric(
+                    &format!("{} {}", label, name),
+                    self.quantile(q).unwrap_or(0.0),
+                );
+            }
+        }
+
+        let mut table = Table::new(title, &["From", "To", "Reads"]);
+        let mut x = Vec::new();
+        let mut y = Vec::new();
+        for (bin, &count) in self.counts.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            let from = self.edge(bin);
+            table.push_row(vec![from.into(), self.edge(bin + 1).into(), count.into()]);
+            x.push(from);
+            y.push(count as f64);
+        }
+
+        report
+            .with_table(table)
+            .with_series(Series::new(title, name, "Reads", x, y))
+    }
+}
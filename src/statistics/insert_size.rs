@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::paired::{PairedStatistic, downcast_paired};
+use crate::report::{Report, Series, Table};
+use crate::runner::FastqRecord;
+use crate::statistics::adapter_content::Adapter;
+use crate::utils::reverse_complement;
+
+/// Shortest overlap between R1 and the reverse complement of R2 that is accepted.
+pub const MIN_OVERLAP: usize = 30;
+// Mismatches allowed in an overlap, at most one in every `MISMATCH_SPACING` bases.
+const MAX_MISMATCHES: usize = 5;
+const MISMATCH_SPACING: usize = 5;
+// Read-through shorter than this is not compared to the adapters.
+const MIN_ADAPTER_MATCH: usize = 6;
+
+// Number of differing bases, positions with an `N` in either sequence are not counted.
+fn mismatches(a: &[u8], b: &[u8]) -> usize {
+    a.iter()
+        .zip(b)
+        .filter(|&(&x, &y)| {
+            let (x, y) = (x.to_ascii_uppercase(), y.to_ascii_uppercase());
+            x != y && x != b'N' && y != b'N'
+        })
+        .count()
+}
+
+fn is_overlap(a: &[u8], b: &[u8]) -> bool {
+    a.len() >= MIN_OVERLAP && mismatches(a, b) <= MAX_MISMATCHES.min(a.len() / MISMATCH_SPACING)
+}
+
+/// Length of the fragment of a read pair, estimated from the overlap of
+/// `r1` with the reverse complement of `r2`. `None` if the mates do not
+/// overlap by at least [`MIN_OVERLAP`] bases.
+///
+/// Fragments shorter than a read are found as well, the read then continues
+/// into the adapter after the fragment.
+pub fn fragment_length(r1: &[u8], r2: &[u8]) -> Option<usize> {
+    let rc = reverse_complement(r2);
+    // The reverse complement of R2 starts `offset` bases into R1 ...
+    for offset in 0..r1.len().saturating_sub(MIN_OVERLAP - 1) {
+        let len = (r1.len() - offset).min(rc.len());
+        if is_overlap(&r1[offset..offset + len], &rc[..len]) {
+            return Some(offset + rc.len());
+        }
+    }
+    // ... or before R1, if the fragment is shorter than R2
+    for before in 1..rc.len().saturating_sub(MIN_OVERLAP - 1) {
+        let len = r1.len().min(rc.len() - before);
+        if is_overlap(&r1[..len], &rc[before..before + len]) {
+            return Some(rc.len() - before);
+        }
+    }
+    None
+}
+
+/// Fragment length distribution and adapter read-through of read pairs,
+/// estimated without alignment from the overlap of the mates.
+///
+/// For pairs whose fragment is shorter than a read, the bases after the
+/// fragment are compared to the adapters to tell which one was read.
+#[derive(Serialize, Deserialize)]
+pub struct InsertSize {
+    adapters: Vec<Adapter>,
+    pairs: u64,
+    // Number of overlapping pairs for every fragment length
+    fragments: BTreeMap<usize, u64>,
+    // Pairs in which R1 and R2 read past the end of the fragment
+    read_through: [u64; 2],
+    // Read-through starting with each adapter for R1 and R2, the last entry
+    // for other sequences
+    adapter_hits: Vec<[u64; 2]>,
+}
+
+impl Default for InsertSize {
+    fn default() -> Self {
+        InsertSize::new()
+    }
+}
+
+impl InsertSize {
+    pub fn new() -> Self {
+        InsertSize::with_adapters(Adapter::builtin())
+    }
+
+    /// Compare the read-through to `adapters`.
+    pub fn with_adapters(adapters: Vec<Adapter>) -> Self {
+        InsertSize {
+            adapter_hits: vec![[0; 2]; adapters.len() + 1],
+            adapters,
+            pairs: 0,
+            fragments: BTreeMap::new(),
+            read_through: [0; 2],
+        }
+    }
+
+    /// Number of overlapping pairs for every fragment length.
+    pub fn fragment_lengths(&self) -> &BTreeMap<usize, u64> {
+        &self.fragments
+    }
+
+    /// Pairs whose mates overlap.
+    pub fn overlapping(&self) -> u64 {
+        self.fragments.values().sum()
+    }
+
+    /// Pairs in which R1 and R2 read into the adapter.
+    pub fn read_through(&self) -> [u64; 2] {
+        self.read_through
+    }
+
+    // Index of the adapter the read-through `tail` starts with, the last
+    // index if it matches none. `None` if it is too short to tell.
+    fn classify(&self, tail: &[u8]) -> Option<usize> {
+        if tail.len() < MIN_ADAPTER_MATCH {
+            return None;
+        }
+        let index = self.adapters.iter().position(|adapter| {
+            let len = tail.len().min(adapter.seq.len());
+            tail[..len].eq_ignore_ascii_case(&adapter.seq[..len])
+        });
+        Some(index.unwrap_or(self.adapters.len()))
+    }
+}
+
+#[typetag::serde]
+impl PairedStatistic for InsertSize {
+    fn process(&mut self, r1: &FastqRecord, r2: &FastqRecord) {
+        self.pairs += 1;
+        let Some(fragment) = fragment_length(&r1.seq, &r2.seq) else {
+            return;
+        };
+        *self.fragments.entry(fragment).or_insert(0) += 1;
+        for (mate, seq) in [&r1.seq, &r2.seq].into_iter().enumerate() {
+            if fragment < seq.len() {
+                self.read_through[mate] += 1;
+                if let Some(adapter) = self.classify(&seq[fragment..]) {
+                    self.adapter_hits[adapter][mate] += 1;
+                }
+            }
+        }
+    }
+
+    fn report(&self) -> Report {
+        let overlapping = self.overlapping();
+        let percent = |n: u64| n as f64 / self.pairs as f64 * 100.0;
+
+        let mut lengths = Table::new("Fragment lengths", &["Length", "Pairs"]);
+        for (&len, &count) in &self.fragments {
+            lengths.push_row(vec![len.into(), count.into()]);
+        }
+
+        let mut adapters = Table::new("Read-through adapters", &["Adapter", "R1", "R2"]);
+        let names = self
+            .adapters
+            .iter()
+            .map(|a| a.name.as_str())
+            .chain(["Other"]);
+        for (name, hits) in names.zip(&self.adapter_hits) {
+            if hits.iter().any(|&n| n > 0) {
+                adapters.push_row(vec![name.into(), hits[0].into(), hits[1].into()]);
+            }
+        }
+
+        let mut report = Report::new("InsertSize")
+            .with_metric("Pairs", self.pairs)
+            .with_metric("Overlapping pairs", overlapping);
+        if self.pairs > 0 {
+            report = report.with_metric("Overlapping pairs (%)", percent(overlapping));
+        }
+        if overlapping > 0 {
+            let sum: u64 = self
+                .fragments
+                .iter()
+                .map(|(&len, &count)| len as u64 * count)
+                .sum();
+            // Lower median of the overlapping pairs
+            let mut seen = 0;
+            let median = self
+                .fragments
+                .iter()
+                .find(|&(_, &count)| {
+                    seen += count;
+                    seen * 2 >= overlapping
+                })
+                .map(|(&len, _)| len)
+                .unwrap_or(0);
+            report = report
+                .with_metric("Mean fragment length", sum as f64 / overlapping as f64)
+                .with_metric("Median fragment length", median);
+        }
+        report = report
+            .with_metric("R1 adapter read-through", self.read_through[0])
+            .with_metric("R2 adapter read-through", self.read_through[1]);
+        if self.pairs > 0 {
+            report = report.with_metric(
+                "Adapter read-through (%)",
+                percent(self.read_through[0].max(self.read_through[1])),
+            );
+        }
+
+        report
+            .with_table(lengths)
+            .with_table(adapters)
+            .with_series(Series::new(
+                "Fragment length distribution",
+                "Fragment Length",
+                "Pairs",
+                self.fragments.keys().map(|&l| l as f64).collect(),
+                self.fragments.values().map(|&c| c as f64).collect(),
+            ))
+    }
+
+    fn empty(&self) -> Box<dyn PairedStatistic> {
+        Box::new(InsertSize::with_adapters(self.adapters.clone()))
+    }
+
+    fn merge(&mut self, other: &dyn PairedStatistic) {
+        let other = downcast_paired::<Self>(other);
+        assert_eq!(
+            self.adapters, other.adapters,
+            "Cannot merge insert sizes of different adapters"
+        );
+        self.pairs += other.pairs;
+        for (&len, &count) in &other.fragments {
+            *self.fragments.entry(len).or_insert(0) += count;
+        }
+        for mate in 0..2 {
+            self.read_through[mate] += other.read_through[mate];
+            for (hits, other) in self.adapter_hits.iter_mut().zip(&other.adapter_hits) {
+                hits[mate] += other[mate];
+            }
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::report::{Report, Series, Table};
+use crate::runner::{FastqRecord, Statistic, downcast};
+
+/// Length of the k-mers counted by default.
+pub const DEFAULT_KMER_SIZE: usize = 7;
+/// Longest supported k-mer, as in FastQC.
+pub const MAX_KMER_SIZE: usize = 10;
+
+// Number of read positions grouped into one window.
+const WINDOW: usize = 5;
+// Upper limit of counters, i.e. possible k-mers times windows. It bounds the
+// memory use, k-mers that start in later windows are not counted.
+const MAX_COUNTS: usize = 1 << 24;
+// A k-mer is reported if it is enriched at least this much in one window ...
+const MIN_RATIO: f64 = 5.0;
+// ... with a p-value (corrected for the number of possible k-mers) below this.
+const MAX_P_VALUE: f64 = 0.01;
+// Number of hits reported, and plotted.
+const TOP_HITS: usize = 20;
+const PLOTTED_HITS: usize = 6;
+
+// 2-bit code of a base, `None` for N and other characters.
+fn base_code(base: u8) -> Option<u32> {
+    match base {
+        b'A' | b'a' => Some(0),
+        b'C' | b'c' => Some(1),
+        b'G' | b'g' => Some(2),
+        b'T' | b't' => Some(3),
+        _ => None,
+    }
+}
+
+fn decode(mut code: u32, k: usize) -> String {
+    let mut kmer = vec![b'A'; k];
+    for base in kmer.iter_mut().rev() {
+        *base = b"ACGT"[(code & 3) as usize];
+        code >>= 2;
+    }
+    String::from_utf8(kmer).unwrap()
+}
+
+// Complementary error function, with a fractional error below 1.2e-7
+// (Numerical Recipes, `erfcc`).
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = -1.26551223
+        + t * (1.00002368
+            + t * (0.37409196
+                + t * (0.09678418
+                    + t * (-0.18628806
+                        + t * (0.27886807
+                            + t * (-1.13520398
+                                + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277))))))));
+    let r = t * (-z * z + poly).exp();
+    if x >= 0.0 { r } else { 2.0 - r }
+}
+
+// Up to this many trials the binomial tail is summed exactly.
+const EXACT_TRIALS: u64 = 1000;
+
+// P(X >= observed) for X ~ Binomial(trials, p). Exact for few trials, by the
+// normal approximation with continuity correction otherwise.
+fn binomial_upper_tail(observed: u64, trials: u64, p: f64) -> f64 {
+    if observed == 0 || p >= 1.0 {
+        return 1.0;
+    }
+    if observed > trials || p <= 0.0 {
+        return 0.0;
+    }
+
+    if trials <= EXACT_TRIALS {
+        // P(X = observed) in log space, then the following terms by recurrence
+        let (n, k) = (trials as f64, observed as f64);
+        let ln_choose: f64 = (1..=observed)
+            .map(|i| ((n - k + i as f64) / i as f64).ln())
+            .sum();
+        let mut term = (ln_choose + k * p.ln() + (n - k) * (1.0 - p).ln()).exp();
+        let mut sum = 0.0;
+        for x in observed..=trials {
+            sum += term;
+            term *= (n - x as f64) / (x as f64 + 1.0) * p / (1.0 - p);
+        }
+        return sum.min(1.0);
+    }
+
+    let mean = trials as f64 * p;
+    let sd = (mean * (1.0 - p)).sqrt();
+    let z = (observed as f64 - 0.5 - mean) / sd;
+    0.5 * erfc(z / std::f64::consts::SQRT_2)
+}
+
+/// A k-mer that is enriched in a part of the reads.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KmerHit {
+    pub kmer: String,
+    pub count: u64,
+    pub p_value: f64,
+    /// Highest ratio of observed to expected count in a window.
+    pub max_ratio: f64,
+    /// 1-based first and last position of the window with the highest ratio.
+    pub peak: (usize, usize),
+    // Observed to expected ratio in every window
+    ratios: Vec<f64>,
+}
+
+/// Finds k-mers whose positional distribution is strongly biased, as in FastQC.
+///
+/// The k-mers are counted in windows of 5 positions. A k-mer is enriched in a
+/// window if it starts there more often than expected from the share of all
+/// k-mers starting in that window, tested with a binomial test. K-mers with
+/// `N` are skipped. Only the windows up to [`KmerContent::max_positions`] are
+/// counted, so memory use does not depend on the read length.
+#[derive(Serialize, Deserialize)]
+pub struct KmerContent {
+    k: usize,
+    // counts[kmer][window]: occurrences of a k-mer per window
+    counts: HashMap<u32, Vec<u64>>,
+    // All k-mers counted per window
+    window_totals: Vec<u64>,
+}
+
+impl Default for KmerContent {
+    fn default() -> Self {
+        KmerContent::new()
+    }
+}
+
+impl KmerContent {
+    pub fn new() -> Self {
+        KmerContent::with_kmer_size(DEFAULT_KMER_SIZE)
+    }
+
+    /// Count k-mers of length `k`, 1 to [`MAX_KMER_SIZE`].
+    pub fn with_kmer_size(k: usize) -> Self {
+        assert!(
+            (1..=MAX_KMER_SIZE).contains(&k),
+            "Unsupported k-mer size {}",
+            k
+        );
+        KmerContent {
+            k,
+            counts: HashMap::new(),
+            window_totals: Vec::new(),
+        }
+    }
+
+    /// Number of read positions in which k-mers are counted, 5 120 for the
+    /// default k-mer size and 80 for the longest.
+    pub fn max_positions(&self) -> usize {
+        self.max_windows() * WINDOW
+    }
+
+    fn max_windows(&self) -> usize {
+        MAX_COUNTS >> (2 * self.k)
+    }
+
+    /// The enriched k-mers, the most significant first.
+    pub fn hits(&self) -> Vec<KmerHit> {
+        let total: u64 = self.window_totals.iter().sum();
+        // Bonferroni correction for the number of possible k-mers
+        let tests = 4f64.powi(self.k as i32);
+
+        let mut hits = Vec::new();
+        for (&code, counts) in &self.counts {
+            let count: u64 = counts.iter().sum();
+            let mut best: Option<(f64, f64, usize)> = None;
+            let mut ratios = vec![0.0; self.window_totals.len()];
+            for (window, &observed) in counts.iter().enumerate() {
+                let p = self.window_totals[window] as f64 / total as f64;
+                let ratio = observed as f64 / (count as f64 * p);
+                ratios[window] = ratio;
+                if ratio < MIN_RATIO {
+                    continue;
+                }
+                let p_value = (binomial_upper_tail(observed, count, p) * tests).min(1.0);
+                if p_value < MAX_P_VALUE && best.is_none_or(|(max, _, _)| ratio > max) {
+                    best = Some((ratio, p_value, window));
+                }
+            }
+            if let Some((max_ratio, p_value, window)) = best {
+                hits.push(KmerHit {
+                    kmer: decode(code, self.k),
+                    count,
+                    p_value,
+                    max_ratio,
+                    peak: (window * WINDOW + 1, (window + 1) * WINDOW),
+                    ratios,
+                });
+            }
+        }
+        hits.sort_by(|a, b| {
+            a.p_value
+                .total_cmp(&b.p_value)
+                .then_with(|| b.max_ratio.total_cmp(&a.max_ratio))
+                .then_with(|| a.kmer.cmp(&b.kmer))
+        });
+        hits.truncate(TOP_HITS);
+        hits
+    }
+}
+
+#[typetag::serde]
+impl Statistic for KmerContent {
+    fn process(&mut self, record: &FastqRecord) {
+        let k = self.k;
+        if record.seq.len() < k {
+            return;
+        }
+        let windows = ((record.seq.len() - k) / WINDOW + 1).min(self.max_windows());
+        if self.window_totals.len() < windows {
+            self.window_totals.resize(windows, 0);
+        }
+
+        let mask = (1 << (2 * k)) - 1;
+        let mut code = 0;
+        // Number of valid bases at the end of `code`
+        let mut valid = 0;
+        for (i, &base) in record.seq.iter().enumerate() {
+            let Some(bits) = base_code(base) else {
+                valid = 0;
+                continue;
+            };
+            code = ((code << 2) | bits) & mask;
+            valid += 1;
+            if valid >= k {
+                let window = (i + 1 - k) / WINDOW;
+                if window >= windows {
+                    break;
+                }
+                let counts = self.counts.entry(code).or_default();
+                if counts.len() <= window {
+                    counts.resize(window + 1, 0);
+                }
+                counts[window] += 1;
+                self.window_totals[window] += 1;
+            }
+        }
+    }
+
+    fn report(&self) -> Report {
+        let hits = self.hits();
+
+        let mut table = Table::new(
+            "Enriched k-mers",
+            &[
+                "Sequence",
+                "Count",
+                "P-value",
+                "Obs/Exp Max",
+                "Max Obs/Exp Position",
+            ],
+        );
+        for hit in &hits {
+            table.push_row(vec![
+                hit.kmer.as_str().into(),
+                hit.count.into(),
+                hit.p_value.into(),
+                hit.max_ratio.into(),
+                format!("{}-{}", hit.peak.0, hit.peak.1).into(),
+            ]);
+        }
+
+        let mut report = Report::new("KmerContent")
+            .with_metric("K-mer size", self.k)
+            .with_metric("Enriched k-mers", hits.len())
+            .with_table(table);
+        for hit in hits.iter().take(PLOTTED_HITS) {
+            // X-axis: first position of every window
+            let positions = (0..hit.ratios.len())
+                .map(|w| (w * WINDOW + 1) as f64)
+                .collect();
+            report = report.with_series(Series::new(
+                &hit.kmer,
+                "Position",
+                "Obs/Exp",
+                positions,
+                hit.ratios.clone(),
+            ));
+        }
+        report
+    }
+
+    fn empty(&self) -> Box<dyn Statistic> {
+        Box::new(KmerContent::with_kmer_size(self.k))
+    }
+
+    fn merge(&mut self, other: &dyn Statistic) {
+        let other = downcast::<Self>(other);
+        assert_eq!(self.k, other.k, "Cannot merge k-mers of different length");
+        for (&code, other) in &other.counts {
+            let counts = self.counts.entry(code).or_default();
+            if counts.len() < other.len() {
+                counts.resize(other.len(), 0);
+            }
+            for (count, &n) in counts.iter_mut().zip(other) {
+                *count += n;
+            }
+        }
+        if self.window_totals.len() < other.window_totals.len() {
+            self.window_totals.resize(other.window_totals.len(), 0);
+        }
+        for (total, &n) in self.window_totals.iter_mut().zip(&other.window_totals) {
+            *total += n;
+        }
+    }
+}
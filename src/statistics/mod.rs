@@ -1,5 +1,15 @@
+pub mod adapter_content;
 pub mod base_count_per_pos;
 pub mod base_count_per_read;
+pub mod by_lane;
+pub mod duplication;
+pub mod histogram;
+pub mod insert_size;
+pub mod kmer_content;
+pub mod overrepresented;
 pub mod phred_per_pos;
 pub mod phred_per_read;
+pub mod phred_per_seq;
 pub mod read_data;
+pub mod sequence_counter;
+pub mod tile_quality;
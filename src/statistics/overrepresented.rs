@@ -0,0 +1,243 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::report::{Report, Table};
+use crate::runner::{FastqRecord, Statistic, downcast};
+use crate::statistics::duplication::DEFAULT_UNIQUE_LIMIT;
+use crate::statistics::sequence_counter::SequenceCounter;
+use crate::utils::reverse_complement;
+
+/// Fraction of all reads above which a sequence is reported by default.
+pub const DEFAULT_THRESHOLD: f64 = 0.001;
+
+// Shortest exact match between a sequence and a contaminant that is reported.
+const MIN_MATCH: usize = 20;
+// Shorter sequences are not matched against the contaminants at all.
+const MIN_QUERY: usize = 8;
+
+// Adapters and primers that commonly show up in Illumina libraries.
+const BUILTIN: [(&str, &str); 15] = [
+    (
+        "Illumina Single End Adapter 1",
+        "GATCGGAAGAGCTCGTATGCCGTCTTCTGCTTG",
+    ),
+    (
+        "Illumina Paired End Adapter 1",
+        "ACACTCTTTCCCTACACGACGCTCTTCCGATCT",
+    ),
+    (
+        "Illumina Paired End Adapter 2",
+        "GATCGGAAGAGCGGTTCAGCAGGAATGCCGAG",
+    ),
+    (
+        "Illumina Paired End PCR Primer 2",
+        "CAAGCAGAAGACGGCATACGAGATCGGTCTCGGCATTCCTGCTGAACCGCTCTTCCGATCT",
+    ),
+    (
+        "TruSeq Universal Adapter",
+        "AATGATACGGCGACCACCGAGATCTACACTCTTTCCCTACACGACGCTCTTCCGATCT",
+    ),
+    (
+        "TruSeq Adapter, Index 1",
+        "GATCGGAAGAGCACACGTCTGAACTCCAGTCACATCACGATCTCGTATGCCGTCTTCTGCTTG",
+    ),
+    ("Illumina Small RNA 3' Adapter", "TGGAATTCTCGGGTGCCAAGG"),
+    (
+        "Illumina Small RNA 5' Adapter",
+        "GTTCAGAGTTCTACAGTCCGACGATC",
+    ),
+    (
+        "Illumina Small RNA RT Primer",
+        "CAAGCAGAAGACGGCATACGAGATCGTGATGTGACTGGAGTTCCTTGGCACCCGAGAATTCCA",
+    ),
+    (
+        "Nextera Transposase Sequence 1",
+        "TCGTCGGCAGCGTCAGATGTGTATAAGAGACAG",
+    ),
+    (
+        "Nextera Transposase Sequence 2",
+        "GTCTCGTGGGCTCGGAGATGTGTATAAGAGACAG",
+    ),
+    (
+        "Illumina Multiplexing Index Read Sequencing Primer",
+        "GATCGGAAGAGCACACGTCTGAACTCCAGTCAC",
+    ),
+    (
+        "Clontech SMARTer II A Oligonucleotide",
+        "AAGCAGTGGTATCAACGCAGAGTAC",
+    ),
+    ("Poly A", "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAA"),
+    ("Poly G", "GGGGGGGGGGGGGGGGGGGGGGGGGGGGGG"),
+];
+
+/// A known sequence that overrepresented sequences are compared to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Contaminant {
+    pub name: String,
+    pub seq: Vec<u8>,
+}
+
+impl Contaminant {
+    pub fn new(name: &str, seq: &[u8]) -> Self {
+        Contaminant {
+            name: name.to_string(),
+            seq: seq.to_ascii_uppercase(),
+        }
+    }
+
+    /// The built-in list of common adapters and primers.
+    pub fn builtin() -> Vec<Contaminant> {
+        BUILTIN
+            .iter()
+            .map(|(name, seq)| Contaminant::new(name, seq.as_bytes()))
+            .collect()
+    }
+}
+
+// Length of the longest common substring of `a` and `b`.
+fn longest_common_substring(a: &[u8], b: &[u8]) -> usize {
+    let mut previous = vec![0; b.len() + 1];
+    let mut current = vec![0; b.len() + 1];
+    let mut longest = 0;
+    for &x in a {
+        for (j, &y) in b.iter().enumerate() {
+            current[j + 1] = if x == y { previous[j] + 1 } else { 0 };
+            longest = longest.max(current[j + 1]);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    longest
+}
+
+/// Sequences that make up more than `threshold` of all reads.
+///
+/// Each is reported with its possible source, the contaminant sharing the
+/// longest exact match of at least 20 bases with it (in either orientation).
+/// Sequences are counted with a [`SequenceCounter`], so reads longer than 75
+/// bases are compared by their first 50 bases.
+#[derive(Serialize, Deserialize)]
+pub struct Overrepresented {
+    counter: SequenceCounter,
+    threshold: f64,
+    #[serde(skip)]
+    contaminants: Arc<Vec<Contaminant>>,
+}
+
+impl Default for Overrepresented {
+    fn default() -> Self {
+        Overrepresented::new()
+    }
+}
+
+impl Overrepresented {
+    pub fn new() -> Self {
+        Overrepresented::with_threshold(DEFAULT_THRESHOLD)
+    }
+
+    /// Report sequences above `threshold` (0.0..=1.0) of all reads.
+    pub fn with_threshold(threshold: f64) -> Self {
+        Overrepresented {
+            counter: SequenceCounter::new(DEFAULT_UNIQUE_LIMIT),
+            threshold,
+            contaminants: Arc::new(Contaminant::builtin()),
+        }
+    }
+
+    /// Track at most `limit` distinct sequences.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.counter = SequenceCounter::new(limit);
+        self
+    }
+
+    /// Also compare the sequences to `contaminants`, e.g. read from a FASTA file.
+    pub fn with_contaminants(mut self, contaminants: Vec<Contaminant>) -> Self {
+        Arc::make_mut(&mut self.contaminants).extend(contaminants);
+        self
+    }
+
+    /// The sequences above the threshold with their counts, the most frequent first.
+    pub fn sequences(&self) -> Vec<(&str, u64)> {
+        let min = self.threshold * self.counter.total() as f64;
+        let mut sequences: Vec<_> = self
+            .counter
+            .iter()
+            .filter(|&(_, count)| count as f64 > min)
+            .collect();
+        sequences.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        sequences
+    }
+
+    /// The contaminant `seq` most likely comes from, with the length of the match.
+    pub fn source(&self, seq: &str) -> Option<(&Contaminant, usize)> {
+        let seq = seq.as_bytes();
+        if seq.len() < MIN_QUERY {
+            return None;
+        }
+        let reverse = reverse_complement(seq);
+        let min = MIN_MATCH.min(seq.len());
+
+        self.contaminants
+            .iter()
+            .map(|contaminant| {
+                let forward = longest_common_substring(seq, &contaminant.seq);
+                let backward = longest_common_substring(&reverse, &contaminant.seq);
+                (contaminant, forward.max(backward))
+            })
+            .filter(|&(_, len)| len >= min)
+            // `max_by_key` keeps the last maximum, prefer the first listed contaminant
+            .rev()
+            .max_by_key(|&(_, len)| len)
+    }
+}
+
+#[typetag::serde]
+impl Statistic for Overrepresented {
+    fn process(&mut self, record: &FastqRecord) {
+        self.counter.add(&record.seq);
+    }
+
+    fn report(&self) -> Report {
+        let total = self.counter.total();
+        let sequences = self.sequences();
+
+        let mut table = Table::new(
+            "Overrepresented sequences",
+            &["Sequence", "Count", "Percentage", "Possible Source"],
+        );
+        for &(seq, count) in &sequences {
+            let source = match self.source(seq) {
+                Some((contaminant, len)) => format!("{} ({} bp match)", contaminant.name, len),
+                None => "No Hit".to_string(),
+            };
+            table.push_row(vec![
+                seq.into(),
+                count.into(),
+                (count as f64 / total as f64 * 100.0).into(),
+                source.into(),
+            ]);
+        }
+
+        Report::new("Overrepresented")
+            .with_metric("Reads", total)
+            .with_metric("Overrepresented sequences", sequences.len())
+            .with_table(table)
+    }
+
+    fn empty(&self) -> Box<dyn Statistic> {
+        Box::new(Overrepresented {
+            counter: SequenceCounter::new(self.counter.limit()),
+            threshold: self.threshold,
+            contaminants: Arc::clone(&self.contaminants),
+        })
+    }
+
+    // The counter keeps the first sequences of the input
+    fn ordered(&self) -> bool {
+        true
+    }
+
+    fn merge(&mut self, other: &dyn Statistic) {
+        self.counter.merge(&downcast::<Self>(other).counter);
+    }
+}
@@ -1,72 +1,205 @@
+use crate::report::{Report, Series, Table};
 use crate::runner::FastqRecord;
 use crate::runner::Statistic;
-use crate::utils::calculate_phred;
-use gnuplot::AxesCommon;
-use gnuplot::Figure;
+use crate::runner::downcast;
+use crate::utils::{QualityMean, calculate_phred, error_probability, phred_from_probability};
 use serde::Deserialize;
 use serde::Serialize;
 
-/// Computes mean base quality for a position read.
+/// Number of distinct Phred scores, 0 to 93.
+pub const PHRED_SCORES: usize = 94;
+
+// Percentiles reported for every position, as in a box plot.
+const PERCENTILES: [(&str, f64); 5] = [
+    ("P10", 0.1),
+    ("Q1", 0.25),
+    ("Median", 0.5),
+    ("Q3", 0.75),
+    ("P90", 0.9),
+];
+
+/// Computes the base quality distribution for every position of the reads.
+///
+/// Keeps a histogram of the Phred scores 0 to 93 per position, so memory is
+/// bounded by the read length and not by the number of reads. Means and
+/// expected errors are computed exactly from these counts.
 #[derive(Default, Serialize, Deserialize)]
 pub struct PhredPerPos {
-    phred_sums: Vec<f32>,
-    amounts: Vec<u64>,
+    // counts[pos][phred]: number of bases with this quality at this position
+    counts: Vec<Vec<u64>>,
+    mean_mode: QualityMean,
+}
+
+// Mean of the qualities counted in `counts`, `None` without any bases.
+fn mean_of(counts: &[u64], mode: QualityMean) -> Option<f64> {
+    let amount: u64 = counts.iter().sum();
+    if amount == 0 {
+        return None;
+    }
+    Some(match mode {
+        QualityMean::Arithmetic => {
+            let sum: u64 = counts.iter().enumerate().map(|(q, &n)| q as u64 * n).sum();
+            sum as f64 / amount as f64
+        }
+        QualityMean::Probability => phred_from_probability(errors_of(counts) / amount as f64),
+    })
+}
+
+// Expected errors of the bases counted in `counts`.
+fn errors_of(counts: &[u64]) -> f64 {
+    counts
+        .iter()
+        .enumerate()
+        .map(|(q, &n)| error_probability(q as f32) * n as f64)
+        .sum()
 }
 
 impl PhredPerPos {
     pub fn new() -> Self {
         PhredPerPos {
-            phred_sums: Vec::new(),
-            amounts: Vec::new(),
+            counts: Vec::new(),
+            mean_mode: QualityMean::Arithmetic,
         }
     }
 
-    fn _out(&self) {
-        let qual_avg: Vec<f32> = self
-            .phred_sums
-            .iter()
-            .zip(&self.amounts)
-            .map(|(&sum, &amount)| sum / amount as f32)
-            .collect();
-
-        let positions: Vec<usize> = (0..qual_avg.len()).collect(); // X-axis: positions
-        let qual_values = &qual_avg; // Y-axis: quality scores
-
-        println!("{}", qual_avg[1]);
-
-        let mut fg = Figure::new();
-        fg.axes2d()
-            .set_title("Base Quality per Position", &[])
-            .set_y_range(
-                gnuplot::AutoOption::Fix(0.0),
-                gnuplot::AutoOption::Fix(38.0),
-            )
-            .set_x_label("Position", &[])
-            .set_y_label("Average Quality Score", &[])
-            .lines(
-                positions,
-                qual_values,
-                &[gnuplot::Caption("Avg Quality"), gnuplot::Color("blue")],
-            );
+    /// Average the qualities of a position as `mean_mode` describes.
+    pub fn with_mean(mut self, mean_mode: QualityMean) -> Self {
+        self.mean_mode = mean_mode;
+        self
+    }
+
+    /// Mean quality at position `pos` (0-based), `None` without any bases.
+    pub fn mean(&self, pos: usize) -> Option<f64> {
+        mean_of(self.counts.get(pos)?, self.mean_mode)
+    }
 
-        fg.show().unwrap(); // Display the plot
+    /// Expected number of wrong base calls at position `pos` (0-based).
+    pub fn expected_errors(&self, pos: usize) -> f64 {
+        self.counts.get(pos).map_or(0.0, |counts| errors_of(counts))
+    }
+
+    /// The `q`-quantile (0.0..=1.0) of the qualities at position `pos` (0-based).
+    pub fn quantile(&self, pos: usize, q: f64) -> Option<u64> {
+        let counts = self.counts.get(pos)?;
+        let amount: u64 = counts.iter().sum();
+        if amount == 0 {
+            return None;
+        }
+        // Nearest rank, the qualities are integers anyway
+        let rank = ((q * amount as f64).ceil() as u64).clamp(1, amount);
+        let mut seen = 0;
+        counts
+            .iter()
+            .position(|&n| {
+                seen += n;
+                seen >= rank
+            })
+            .map(|phred| phred as u64)
     }
 }
 
 #[typetag::serde]
 impl Statistic for PhredPerPos {
     fn process(&mut self, record: &FastqRecord) {
-        // Ensure vectors are large enough
-        if self.phred_sums.len() < record.qual.len() {
-            self.phred_sums.resize(record.qual.len(), 0.0);
-            self.amounts.resize(record.qual.len(), 0);
+        // Ensure there is a histogram for every position
+        if self.counts.len() < record.qual.len() {
+            self.counts.resize(record.qual.len(), vec![0; PHRED_SCORES]);
         }
 
-        // Iterate over the quality scores and update sums and counts
+        // Iterate over the quality scores and count them per position
         for (i, &qual) in record.qual.iter().enumerate() {
             if let Some(phred) = calculate_phred(qual) {
-                self.phred_sums[i] += phred;
-                self.amounts[i] += 1;
+                self.counts[i][(phred as usize).min(PHRED_SCORES - 1)] += 1;
+            }
+        }
+    }
+
+    fn report(&self) -> Report {
+        let average = match self.mean_mode {
+            QualityMean::Arithmetic => "Average Quality",
+            QualityMean::Probability => "Average Quality (Error Prob.)",
+        };
+        let mut columns = vec!["Pos", average];
+        columns.extend(PERCENTILES.iter().map(|(name, _)| *name));
+        let mut table = Table::new("Base quality per position", &columns);
+
+        let mut positions = Vec::new(); // X-axis: positions
+        let mut qual_avg = Vec::new();
+        let mut error_rate = Vec::new();
+        for pos in 0..self.counts.len() {
+            let Some(mean) = self.mean(pos) else {
+                continue;
+            };
+            let mut row = vec![(pos + 1).into(), mean.into()];
+            row.extend(
+                PERCENTILES
+                    .iter()
+                    .map(|&(_, q)| self.quantile(pos, q).unwrap_or(0).into()),
+            );
+            table.push_row(row);
+            positions.push((pos + 1) as f64);
+            qual_avg.push(mean);
+            error_rate
+                .push(self.expected_errors(pos) / self.counts[pos].iter().sum::<u64>() as f64);
+        }
+
+        let mut total = vec![0; PHRED_SCORES];
+        for counts in &self.counts {
+            for (sum, &n) in total.iter_mut().zip(counts) {
+                *sum += n;
+            }
+        }
+        let mut report = Report::new("PhredPerPos");
+        if let Some(mean) = mean_of(&total, self.mean_mode) {
+            let name = match self.mean_mode {
+                QualityMean::Arithmetic => "Mean quality",
+                QualityMean::Probability => "Mean quality (error prob.)",
+            };
+            let errors = errors_of(&total);
+            report = report
+                .with_metric(name, mean)
+                .with_metric("Expected errors", errors)
+                .with_metric(
+                    "Expected errors per base",
+                    errors / total.iter().sum::<u64>() as f64,
+                );
+        }
+
+        report
+            .with_table(table)
+            .with_series(Series::new(
+                "Base Quality per Position",
+                "Position",
+                "Average Quality Score",
+                positions.clone(),
+                qual_avg,
+            ))
+            .with_series(Series::new(
+                "Error Probability per Position",
+                "Position",
+                "Mean Error Probability",
+                positions,
+                error_rate,
+            ))
+    }
+
+    fn empty(&self) -> Box<dyn Statistic> {
+        Box::new(PhredPerPos::new().with_mean(self.mean_mode))
+    }
+
+    fn merge(&mut self, other: &dyn Statistic) {
+        let other = downcast::<Self>(other);
+        assert_eq!(
+            self.mean_mode, other.mean_mode,
+            "Cannot merge base qualities of different means"
+        );
+        if self.counts.len() < other.counts.len() {
+            self.counts
+                .resize(other.counts.len(), vec![0; PHRED_SCORES]);
+        }
+        for (counts, other) in self.counts.iter_mut().zip(&other.counts) {
+            for (count, &n) in counts.iter_mut().zip(other) {
+                *count += n;
             }
         }
     }
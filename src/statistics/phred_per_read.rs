@@ -1,75 +1,104 @@
-use crate::runner::{FastqRecord, Statistic};
-use crate::utils::{ask_for_len, calculate_phred};
-use gnuplot::AxesCommon;
-use gnuplot::Figure;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::paired::mate_name;
+use crate::report::{Report, Series};
+use crate::runner::{FastqRecord, Statistic, downcast};
+use crate::statistics::histogram::Histogram;
+use crate::utils::{QualityMean, calculate_phred, error_probability};
 use serde::{Deserialize, Serialize};
 
-/// Computes mean base quality for a read.
-#[derive(Default, Serialize, Deserialize)]
-pub struct PhredPerRead {
-    mean: Vec<f32>,
+/// Number of per-read means kept for plotting by default.
+pub const DEFAULT_TRACE_LEN: usize = 10_000;
+
+// The mean quality of a read kept for plotting, ordered by `key` and `read`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct TracePoint {
+    // Hash of the record, the reads with the smallest keys are kept
+    key: u64,
+    // 0-based number of the read
+    read: u64,
+    mean: f32,
 }
 
-impl PhredPerRead {
-    pub fn new() -> Self {
-        PhredPerRead { mean: Vec::new() }
+impl Ord for TracePoint {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.key, self.read).cmp(&(other.key, other.read))
     }
+}
 
-    fn _out(&self) {
-        let read_nr: Vec<usize> = (0..self.mean.len()).collect(); // X-axis: positions
-        let qual_values = &self.mean; // Y-axis: quality scores
+impl PartialOrd for TracePoint {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for TracePoint {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
 
-        println!("\nPlotting the average quality per read");
+impl Eq for TracePoint {}
 
-        let start = match ask_for_len("\n   Input start position if desired: ") {
-            Ok(num) => num,
-            Err(_) => {
-                println!("\tInvalid start position starting at 0\n");
-                0
-            }
-        };
+/// Computes mean base quality and expected errors for a read.
+///
+/// The means are collected in a histogram with bins of 0.1, so memory does
+/// not grow with the number of reads. The same holds for the expected errors,
+/// the sum of the error probabilities of the bases, whose histogram has bins
+/// on a log scale so that long reads are covered as well.
+#[derive(Serialize, Deserialize)]
+pub struct PhredPerRead {
+    mean: Histogram,
+    mean_mode: QualityMean,
+    expected_errors: Histogram,
+    // Per-read means of a sample of `trace_len` reads from the whole input,
+    // kept for plotting. As the keys are hashes of the records, this is a
+    // uniform sample that does not depend on how the input was split up.
+    trace: BTreeSet<TracePoint>,
+    trace_len: usize,
+}
 
-        let end = match ask_for_len("   Input end position if desired: ") {
-            Ok(num) => num,
-            Err(_) => {
-                println!("\tInvalid end position ending at the last read");
-                qual_values.len() as u32 - 1
-            }
-        };
+impl Default for PhredPerRead {
+    fn default() -> Self {
+        PhredPerRead::new()
+    }
+}
 
-        let (plot_start, plot_end) = if start <= end && end < qual_values.len() as u32 {
-            (start as usize, end as usize)
-        } else {
-            println!("Invalid range, displaying full plot");
-            (0, qual_values.len() - 1)
-        };
+impl PhredPerRead {
+    pub fn new() -> Self {
+        PhredPerRead::with_trace_len(DEFAULT_TRACE_LEN)
+    }
+
+    /// Keep the means of `trace_len` reads sampled from the whole input for
+    /// plotting, 0 disables the trace.
+    pub fn with_trace_len(trace_len: usize) -> Self {
+        PhredPerRead {
+            mean: Histogram::new(0.0, 0.1, 940),
+            mean_mode: QualityMean::Arithmetic,
+            // From below 0.001 for short high quality reads to 1,000,000 for long reads
+            expected_errors: Histogram::log(0.001, 100, 9),
+            trace: BTreeSet::new(),
+            trace_len,
+        }
+    }
+
+    /// Average the qualities of a read as `mean_mode` describes.
+    pub fn with_mean(mut self, mean_mode: QualityMean) -> Self {
+        self.mean_mode = mean_mode;
+        self
+    }
 
-        if start <= end && end < qual_values.len() as u32 {
-            let mut fg = Figure::new();
-            fg.axes2d()
-                .set_title("Avg Quality per Read", &[])
-                .set_x_label("Read Number", &[])
-                .set_y_label("Average Quality Score", &[])
-                .lines(
-                    read_nr[plot_start..plot_end].to_vec(),
-                    qual_values[plot_start..plot_end].to_vec(),
-                    &[gnuplot::Caption("Avg Quality"), gnuplot::Color("blue")],
-                );
-
-            fg.show().unwrap(); // Display the plot
-        } else {
-            let mut fg = Figure::new();
-            fg.axes2d()
-                .set_title("Avg Quality per Read", &[])
-                .set_x_label("Read Number", &[])
-                .set_y_label("Average Quality Score", &[])
-                .lines(
-                    read_nr,
-                    qual_values,
-                    &[gnuplot::Caption("Avg Quality"), gnuplot::Color("blue")],
-                );
-
-            fg.show().unwrap(); // Display the plot
+    // Add `point` to the trace if it is among the `trace_len` smallest keys.
+    fn add_trace(&mut self, point: TracePoint) {
+        if self.trace.len() == self.trace_len && self.trace.last().is_none_or(|last| point > *last)
+        {
+            return;
+        }
+        self.trace.insert(point);
+        if self.trace.len() > self.trace_len {
+            self.trace.pop_last();
         }
     }
 }
@@ -84,9 +113,84 @@ impl Statistic for PhredPerRead {
             .filter_map(|&q| calculate_phred(q)) // Convert ASCII to Phred score
             .collect();
 
-        // push the mean of x
-        if !x.is_empty() {
-            self.mean.push(x.iter().sum::<f32>() / x.len() as f32)
+        // add the mean of x
+        if let Some(mean) = self.mean_mode.mean(&x) {
+            // Names make duplicated reads differ, the mate suffix is left
+            // out so that both ways of reading pairs sample the same reads
+            let mut hasher = DefaultHasher::new();
+            (mate_name(&record.name), &record.seq, &record.qual).hash(&mut hasher);
+            self.add_trace(TracePoint {
+                key: hasher.finish(),
+                read: self.mean.total(),
+                mean: mean as f32,
+            });
+            self.mean.add(mean);
+            self.expected_errors
+                .add(x.iter().map(|&q| error_probability(q)).sum());
+        }
+    }
+
+    fn report(&self) -> Report {
+        let (name, suffix) = match self.mean_mode {
+            QualityMean::Arithmetic => ("quality", ""),
+            QualityMean::Probability => ("quality (error prob.)", " (Error Prob.)"),
+        };
+        let mut report = self.mean.report_into(
+            Report::new("PhredPerRead"),
+            name,
+            &format!("Avg Quality Distribution{}", suffix),
+        );
+        if !self.trace.is_empty() {
+            let mut trace: Vec<&TracePoint> = self.trace.iter().collect();
+            trace.sort_unstable_by_key(|p| p.read);
+            let read_nr = trace.iter().map(|p| (p.read + 1) as f64).collect(); // X-axis: reads
+            let qual_values = trace.iter().map(|p| p.mean as f64).collect(); // Y-axis: quality scores
+            report = report.with_series(Series::new(
+                &format!("Avg Quality per Read{}", suffix),
+                "Read Number",
+                "Average Quality Score",
+                read_nr,
+                qual_values,
+            ));
+        }
+
+        let errors = &self.expected_errors;
+        if let (Some(mean), Some(median), Some(max)) =
+            (errors.mean(), errors.median(), errors.max())
+        {
+            report = report
+                .with_metric("Mean expected errors", mean)
+                .with_metric("Median expected errors", median)
+                .with_metric("Max expected errors", max)
+                .with_metric("Total expected errors", mean * errors.total() as f64);
+        }
+        report
+    }
+
+    fn empty(&self) -> Box<dyn Statistic> {
+        Box::new(PhredPerRead::with_trace_len(self.trace_len).with_mean(self.mean_mode))
+    }
+
+    // The trace numbers the reads in input order
+    fn ordered(&self) -> bool {
+        self.trace_len > 0
+    }
+
+    fn merge(&mut self, other: &dyn Statistic) {
+        let other = downcast::<Self>(other);
+        assert_eq!(
+            self.mean_mode, other.mean_mode,
+            "Cannot merge read qualities of different means"
+        );
+        // The reads of `other` come after those of `self`
+        let offset = self.mean.total();
+        for point in &other.trace {
+            self.add_trace(TracePoint {
+                read: point.read + offset,
+                ..*point
+            });
         }
+        self.mean.merge(&other.mean);
+        self.expected_errors.merge(&other.expected_errors);
     }
 }
@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+
+use crate::report::{Report, Series, Table};
+use crate::runner::{FastqRecord, Statistic, downcast};
+use crate::statistics::phred_per_pos::PHRED_SCORES;
+use crate::utils::calculate_phred;
+
+/// Quality cutoffs reported by default.
+pub const DEFAULT_CUTOFFS: [u8; 2] = [20, 30];
+
+/// Per sequence quality scores: the number of reads for every integer mean quality.
+///
+/// The mean quality of a read is rounded down, so a read with a mean of 19.9
+/// counts as 19 and below a cutoff of 20.
+#[derive(Serialize, Deserialize)]
+pub struct PhredPerSeq {
+    // counts[q]: number of reads with an integer mean quality of q
+    counts: Vec<u64>,
+    cutoffs: Vec<u8>,
+}
+
+impl Default for PhredPerSeq {
+    fn default() -> Self {
+        PhredPerSeq::new()
+    }
+}
+
+impl PhredPerSeq {
+    pub fn new() -> Self {
+        PhredPerSeq::with_cutoffs(&DEFAULT_CUTOFFS)
+    }
+
+    /// Also report the fraction of reads with a mean quality below each of `cutoffs`.
+    pub fn with_cutoffs(cutoffs: &[u8]) -> Self {
+        PhredPerSeq {
+            counts: vec![0; PHRED_SCORES],
+            cutoffs: cutoffs.to_vec(),
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// The most frequent mean quality, the lowest one on ties.
+    pub fn mode(&self) -> Option<u8> {
+        let max = *self.counts.iter().max()?;
+        if max == 0 {
+            return None;
+        }
+        self.counts.iter().position(|&n| n == max).map(|q| q as u8)
+    }
+
+    /// Fraction (0.0..=1.0) of reads with a mean quality below `cutoff`.
+    pub fn fraction_below(&self, cutoff: u8) -> Option<f64> {
+        let total = self.total();
+        let below: u64 = self.counts.iter().take(cutoff as usize).sum();
+        (total > 0).then(|| below as f64 / total as f64)
+    }
+}
+
+#[typetag::serde]
+impl Statistic for PhredPerSeq {
+    fn process(&mut self, record: &FastqRecord) {
+        let mut sum = 0;
+        let mut amount = 0;
+        for phred in record.qual.iter().filter_map(|&q| calculate_phred(q)) {
+            sum += phred as u64;
+            amount += 1;
+        }
+        if let Some(mean) = sum.checked_div(amount) {
+            self.counts[mean as usize] += 1;
+        }
+    }
+
+    fn report(&self) -> Report {
+        let mut report = Report::new("PhredPerSeq").with_metric("Reads", self.total());
+        if let Some(mode) = self.mode() {
+            report = report.with_metric("Mode quality", mode as u64);
+        }
+        for &cutoff in &self.cutoffs {
+            if let Some(fraction) = self.fraction_below(cutoff) {
+                report =
+                    report.with_metric(&format!("Reads below Q{} (%)", cutoff), fraction * 100.0);
+            }
+        }
+
+        // Only the range that has reads, like the quality range of the input
+        let first = self.counts.iter().position(|&n| n > 0).unwrap_or(0);
+        let last = self.counts.iter().rposition(|&n| n > 0).unwrap_or(0);
+        let mut table = Table::new("Per sequence quality scores", &["Mean Quality", "Reads"]);
+        let mut x = Vec::new();
+        let mut y = Vec::new();
+        if self.total() > 0 {
+            for (q, &count) in self.counts.iter().enumerate().take(last + 1).skip(first) {
+                table.push_row(vec![q.into(), count.into()]);
+                x.push(q as f64);
+                y.push(count as f64);
+            }
+        }
+
+        report.with_table(table).with_series(Series::new(
+            "Per Sequence Quality Scores",
+            "Mean Sequence Quality",
+            "Reads",
+            x,
+            y,
+        ))
+    }
+
+    fn empty(&self) -> Box<dyn Statistic> {
+        Box::new(PhredPerSeq::with_cutoffs(&self.cutoffs))
+    }
+
+    fn merge(&mut self, other: &dyn Statistic) {
+        let other = downcast::<Self>(other);
+        for (count, &n) in self.counts.iter_mut().zip(&other.counts) {
+            *count += n;
+        }
+    }
+}
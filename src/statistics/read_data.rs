@@ -1,16 +1,33 @@
-use crate::runner::{FastqRecord, Statistic};
+use std::collections::BTreeMap;
+
+use crate::report::{Report, Series, Table};
+use crate::runner::{FastqRecord, Statistic, downcast};
 use serde::{Deserialize, Serialize};
 
+/// Distribution of read lengths.
 #[derive(Default, Serialize, Deserialize)]
 pub struct ReadData {
-    read_lengths: Vec<(usize, usize)>,
+    // Number of reads for every observed length, sorted by length
+    read_lengths: BTreeMap<usize, u64>,
 }
 
 impl ReadData {
     pub fn new() -> Self {
         ReadData {
-            read_lengths: Vec::new(),
+            read_lengths: BTreeMap::new(),
+        }
+    }
+
+    // Length of the read at the given 0-based rank in length order.
+    fn length_at(&self, rank: u64) -> usize {
+        let mut seen = 0;
+        for (&len, &count) in &self.read_lengths {
+            seen += count;
+            if seen > rank {
+                return len;
+            }
         }
+        0
     }
 }
 
@@ -18,11 +35,49 @@ impl ReadData {
 impl Statistic for ReadData {
     fn process(&mut self, record: &FastqRecord) {
 
-        let len = record.seq.len();
-        if let Some(pos) = self.read_lengths.iter().position(|(l, _)| *l == len) {
-            self.read_lengths[pos].1 += 1;
-        } else {
-            self.read_lengths.push((len, 1));
+        *self.read_lengths.entry(record.seq.len()).or_insert(0) += 1;
+    }
+
+    fn report(&self) -> Report {
+        let reads: u64 = self.read_lengths.values().sum();
+        let bases: u64 = self
+            .read_lengths
+            .iter()
+            .map(|(&len, &count)| len as u64 * count)
+            .sum();
+
+        let mut table = Table::new("Read lengths", &["Length", "Count"]);
+        for (&len, &count) in &self.read_lengths {
+            table.push_row(vec![len.into(), count.into()]);
+        }
+
+        let mut report = Report::new("ReadData").with_metric("Reads", reads);
+        if reads > 0 {
+            // Median of an even number of reads is the mean of the two middle ones
+            let median = (self.length_at((reads - 1) / 2) + self.length_at(reads / 2)) as f64 / 2.0;
+            report = report
+                .with_metric("Min length", *self.read_lengths.keys().next().unwrap())
+                .with_metric("Max length", *self.read_lengths.keys().next_back().unwrap())
+                .with_metric("Mean length", bases as f64 / reads as f64)
+                .with_metric("Median length", median);
+        }
+
+        report.with_table(table).with_series(Series::new(
+            "Read length distribution",
+            "Length",
+            "Reads",
+            self.read_lengths.keys().map(|&l| l as f64).collect(),
+            self.read_lengths.values().map(|&c| c as f64).collect(),
+        ))
+    }
+
+    fn empty(&self) -> Box<dyn Statistic> {
+        Box::new(ReadData::new())
+    }
+
+    fn merge(&mut self, other: &dyn Statistic) {
+        for (&len, &count) in &downcast::<Self>(other).read_lengths {
+            *self.read_lengths.entry(len).or_insert(0) += count;
         }
     }
 }
@@ -0,0 +1,140 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+// Reads longer than this are only compared by their first `TRUNCATE_TO` bases.
+const LONG_READ: usize = 75;
+const TRUNCATE_TO: usize = 50;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Seen {
+    // 0-based index of the read the sequence first appeared in
+    first: u64,
+    count: u64,
+}
+
+/// Counts how often the distinct sequences of the reads occur, as in FastQC.
+///
+/// Only the first `limit` distinct sequences are tracked, later occurrences of
+/// them are still counted. Reads longer than 75 bases are compared by their
+/// first 50 bases.
+///
+/// Merging is exact as long as the later part has not reached the limit on
+/// its own, which holds for the chunks of a parallel scan with the default
+/// limit. Once it has, the later part has missed occurrences of sequences
+/// that only the earlier part tracks, so several inputs have to be counted
+/// one after the other with the same counter rather than merged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SequenceCounter {
+    sequences: HashMap<String, Seen>,
+    limit: usize,
+    // Reads counted in total and up to the point where the limit was reached
+    total: u64,
+    count_at_limit: u64,
+}
+
+impl SequenceCounter {
+    /// Track at most `limit` distinct sequences.
+    pub fn new(limit: usize) -> Self {
+        SequenceCounter {
+            sequences: HashMap::new(),
+            limit,
+            total: 0,
+            count_at_limit: 0,
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Number of reads counted.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Number of reads counted until the limit of distinct sequences was reached.
+    pub fn count_at_limit(&self) -> u64 {
+        self.count_at_limit
+    }
+
+    /// Number of distinct sequences tracked.
+    pub fn len(&self) -> usize {
+        self.sequences.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sequences.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.sequences.len() >= self.limit
+    }
+
+    /// The tracked sequences with their number of occurrences, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.sequences
+            .iter()
+            .map(|(seq, seen)| (seq.as_str(), seen.count))
+    }
+
+    /// Number of tracked sequences for every number of occurrences.
+    pub fn level_counts(&self) -> BTreeMap<u64, u64> {
+        let mut levels = BTreeMap::new();
+        for seen in self.sequences.values() {
+            *levels.entry(seen.count).or_insert(0) += 1;
+        }
+        levels
+    }
+
+    pub fn add(&mut self, seq: &[u8]) {
+        let seq = if seq.len() > LONG_READ {
+            &seq[..TRUNCATE_TO]
+        } else {
+            seq
+        };
+        let full = self.is_full();
+        let key = String::from_utf8_lossy(seq);
+
+        if let Some(seen) = self.sequences.get_mut(key.as_ref()) {
+            seen.count += 1;
+        } else if !full {
+            self.sequences.insert(
+                key.into_owned(),
+                Seen {
+                    first: self.total,
+                    count: 1,
+                },
+            );
+        }
+        self.total += 1;
+        if !full {
+            self.count_at_limit = self.total;
+        }
+    }
+
+    /// Add the counts of `other`, which follows the reads counted so far.
+    pub fn merge(&mut self, other: &SequenceCounter) {
+        let offset = self.total;
+
+        // Add the new sequences in the order they appeared, until the limit is reached
+        let mut sequences: Vec<_> = other.sequences.iter().collect();
+        sequences.sort_unstable_by_key(|(_, seen)| seen.first);
+        for (seq, seen) in sequences {
+            if let Some(mine) = self.sequences.get_mut(seq) {
+                mine.count += seen.count;
+            } else if !self.is_full() {
+                let first = offset + seen.first;
+                self.sequences.insert(seq.clone(), Seen { first, ..*seen });
+                if self.is_full() {
+                    self.count_at_limit = first + 1;
+                }
+            }
+        }
+
+        self.total += other.total;
+        if !self.is_full() {
+            self.count_at_limit = self.total;
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::header::{HeaderFormat, ReadHeader};
+use crate::report::{Report, Series, Table};
+use crate::runner::{FastqRecord, Statistic, downcast};
+use crate::utils::calculate_phred;
+
+/// Deviation from the mean quality of a position above which a tile is reported.
+pub const DEFAULT_TILE_THRESHOLD: f64 = 5.0;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct TileQuality {
+    reads: u64,
+    phred_sums: Vec<u64>,
+    amounts: Vec<u64>,
+}
+
+impl TileQuality {
+    fn add(&mut self, qual: &[u8]) {
+        self.reads += 1;
+        if self.phred_sums.len() < qual.len() {
+            self.phred_sums.resize(qual.len(), 0);
+            self.amounts.resize(qual.len(), 0);
+        }
+        for (i, &q) in qual.iter().enumerate() {
+            if let Some(phred) = calculate_phred(q) {
+                self.phred_sums[i] += phred as u64;
+                self.amounts[i] += 1;
+            }
+        }
+    }
+
+    fn merge(&mut self, other: &TileQuality) {
+        self.reads += other.reads;
+        if self.phred_sums.len() < other.phred_sums.len() {
+            self.phred_sums.resize(other.phred_sums.len(), 0);
+            self.amounts.resize(other.amounts.len(), 0);
+        }
+        for (i, (&sum, &amount)) in other.phred_sums.iter().zip(&other.amounts).enumerate() {
+            self.phred_sums[i] += sum;
+            self.amounts[i] += amount;
+        }
+    }
+
+    fn mean(&self, pos: usize) -> Option<f64> {
+        let amount = *self.amounts.get(pos)?;
+        (amount > 0).then(|| self.phred_sums[pos] as f64 / amount as f64)
+    }
+}
+
+/// A tile whose quality deviates from the mean of all tiles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileDeviation {
+    pub tile: usize,
+    pub reads: u64,
+    /// 1-based position with the largest deviation.
+    pub position: usize,
+    /// Mean quality of the tile minus the mean quality of all tiles at `position`.
+    pub deviation: f64,
+}
+
+/// Per tile sequence quality, as in FastQC.
+///
+/// The tile is taken from the header of every read, see [`ReadHeader`]. For each
+/// tile and position, the mean quality is compared to the mean quality of
+/// all tiles at that position. Reads without tile information are skipped.
+#[derive(Serialize, Deserialize)]
+pub struct PerTileQuality {
+    tiles: BTreeMap<usize, TileQuality>,
+    threshold: f64,
+    // Reads whose header has no tile information
+    unparsed: u64,
+    // Format of the read headers, detected from the first read
+    #[serde(skip)]
+    format: Option<HeaderFormat>,
+}
+
+impl Default for PerTileQuality {
+    fn default() -> Self {
+        PerTileQuality::new()
+    }
+}
+
+impl PerTileQuality {
+    pub fn new() -> Self {
+        PerTileQuality::with_threshold(DEFAULT_TILE_THRESHOLD)
+    }
+
+    /// Report tiles that deviate by more than `threshold` (in Phred units).
+    pub fn with_threshold(threshold: f64) -> Self {
+        PerTileQuality {
+            tiles: BTreeMap::new(),
+            threshold,
+            unparsed: 0,
+            format: None,
+        }
+    }
+
+    // Mean quality of all tiles for every position.
+    fn means(&self) -> Vec<f64> {
+        let mut total = TileQuality::default();
+        for tile in self.tiles.values() {
+            total.merge(tile);
+        }
+        (0..total.phred_sums.len())
+            .map(|pos| total.mean(pos).unwrap_or(0.0))
+            .collect()
+    }
+
+    /// The largest deviation of every tile from the mean, in tile order.
+    pub fn deviations(&self) -> Vec<TileDeviation> {
+        let means = self.means();
+        self.tiles
+            .iter()
+            .map(|(&tile, quality)| {
+                let mut worst = TileDeviation {
+                    tile,
+                    reads: quality.reads,
+                    position: 1,
+                    deviation: 0.0,
+                };
+                for (pos, &mean) in means.iter().enumerate() {
+                    if let Some(tile_mean) = quality.mean(pos) {
+                        let deviation = tile_mean - mean;
+                        if deviation.abs() > worst.deviation.abs() {
+                            worst.position = pos + 1;
+                            worst.deviation = deviation;
+                        }
+                    }
+                }
+                worst
+            })
+            .collect()
+    }
+
+    /// The tiles deviating by more than the threshold.
+    pub fn deviating_tiles(&self) -> Vec<TileDeviation> {
+        self.deviations()
+            .into_iter()
+            .filter(|d| d.deviation.abs() > self.threshold)
+            .collect()
+    }
+}
+
+#[typetag::serde]
+impl Statistic for PerTileQuality {
+    fn process(&mut self, record: &FastqRecord) {
+        match ReadHeader::parse_detected(record, &mut self.format).and_then(|header| header.tile) {
+            Some(tile) => self.tiles.entry(tile).or_default().add(&record.qual),
+            None => self.unparsed += 1,
+        }
+    }
+
+    fn report(&self) -> Report {
+        let deviations = self.deviations();
+
+        let mut table = Table::new(
+            "Tiles with deviating quality",
+            &["Tile", "Reads", "Position", "Deviation"],
+        );
+        for d in deviations
+            .iter()
+            .filter(|d| d.deviation.abs() > self.threshold)
+        {
+            table.push_row(vec![
+                d.tile.into(),
+                d.reads.into(),
+                d.position.into(),
+                d.deviation.into(),
+            ]);
+        }
+
+        let tiles = deviations.iter().map(|d| d.tile as f64).collect(); // X-axis: tiles
+        let worst = deviations.iter().map(|d| d.deviation).collect();
+        Report::new("PerTileQuality")
+            .with_metric("Tiles", self.tiles.len())
+            .with_metric("Reads without tile", self.unparsed)
+            .with_metric("Deviating tiles", table.rows.len())
+            .with_table(table)
+            .with_series(Series::new(
+                "Largest Quality Deviation per Tile",
+                "Tile",
+                "Deviation from Mean Quality",
+                tiles,
+                worst,
+            ))
+    }
+
+    fn empty(&self) -> Box<dyn Statistic> {
+        Box::new(PerTileQuality::with_threshold(self.threshold))
+    }
+
+    fn merge(&mut self, other: &dyn Statistic) {
+        let other = downcast::<Self>(other);
+        for (&tile, quality) in &other.tiles {
+            self.tiles.entry(tile).or_default().merge(quality);
+        }
+        self.unparsed += other.unparsed;
+    }
+}
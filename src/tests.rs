@@ -1,15 +1,46 @@
-use crate::phred;
-
 #[cfg(test)]
 mod test {
 
-    use crate::phred::avg_qual;
+    use std::io::{self, Read, Write};
+    use std::path::{Path, PathBuf};
+
+    use serde::{Deserialize, Serialize};
 
-    use super::phred::calculate_phred;
+    use crate::encoding::QualityEncoding;
+    use crate::error::{FastqErrorKind, FastqScanError, PairErrorKind};
+    use crate::header::{HeaderFormat, ReadHeader};
+    use crate::input::{Compression, decompress};
+    use crate::paired::{
+        MateCheck, PairedReader, PairedRunner, PairedStatistic, detect_interleaved,
+        downcast_paired, mate_name,
+    };
+    use crate::reader::FastqReader;
+    use crate::report::{Metric, Report, Value, write_tsv};
+    use crate::runner::{FastqRecord, RunSummary, Statistic, WorkflowRunner, downcast};
+    use crate::statistics::{
+        adapter_content::{Adapter, AdapterContent},
+        base_count_per_pos::BaseCountPerPos,
+        base_count_per_read::BaseCountRead,
+        by_lane::ByLane,
+        duplication::Duplication,
+        histogram::Histogram,
+        insert_size::{InsertSize, fragment_length},
+        kmer_content::{self, KmerContent},
+        overrepresented::{Contaminant, Overrepresented},
+        phred_per_pos::PhredPerPos,
+        phred_per_read::PhredPerRead,
+        phred_per_seq::PhredPerSeq,
+        read_data::ReadData,
+        tile_quality::PerTileQuality,
+    };
+    use crate::utils::{
+        QualityMean, SampleMetadata, avg_qual, avg_qual_prob, calculate_phred, expected_errors,
+        process_fastq, read_fasta, reverse_complement,
+    };
 
     #[test]
     fn test_calculate_phred() {
-        let qual: u8 = '&' as u8;
+        let qual: u8 = b'&';
         let expected: f32 = 5.0; // Phred-Score für das Zeichen '&'
         let res = calculate_phred(qual).expect("Invalid Phred Char");
         assert_eq!(expected, res);
@@ -18,7 +49,7 @@ mod test {
     // Alternativ können viele Ergebnisse auf einmal getestet werden:
     #[test]
     fn test_calculate_phred_other() {
-        let tests: Vec<(u8, f32)> = vec![('&' as u8, 5.0), ('+' as u8, 10.0)];
+        let tests: Vec<(u8, f32)> = vec![(b'&', 5.0), (b'+', 10.0)];
         for test in tests {
             let res = calculate_phred(test.0).expect("Invalid Phred Char");
             assert!(
@@ -31,7 +62,7 @@ mod test {
     }
 
     #[test]
-    fn test_avg_qual(){
+    fn test_avg_qual() {
         const TEST_STRING: &[u8] = b"/&%/&)/%%";
         const EXPECT: f32 = 8.0;
         let res = avg_qual(TEST_STRING).expect("Expected Some(f32) but got none");
@@ -42,4 +73,1520 @@ mod test {
             res
         );
     }
-}
\ No newline at end of file
+
+    // Parse every record of `data`, returning the records or the first error.
+    fn parse_all(data: &[u8]) -> Result<Vec<FastqRecord>, crate::error::FastqError> {
+        let mut reader = FastqReader::new(data);
+        let mut record = FastqRecord::default();
+        let mut records = Vec::new();
+        while reader.read_record(&mut record)? {
+            records.push(record.clone());
+        }
+        Ok(records)
+    }
+
+    #[test]
+    fn test_parse_valid_records() {
+        let data = b"@r1 comment\nACGT\n+\nIIII\n@r2\r\nNNA\r\n+r2\r\n#!I\r\n";
+        let records = parse_all(data).expect("Valid FASTQ");
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].seq, b"ACGT");
+        assert_eq!(records[0].qual, b"IIII");
+        assert_eq!(records[1].seq, b"NNA");
+        assert_eq!(records[1].qual, b"#!I");
+    }
+
+    #[test]
+    fn test_parse_length_mismatch() {
+        let data = b"@r1\nACGT\n+\nIIII\n@r2\nACGT\n+\nIII\n";
+        let err = parse_all(data).expect_err("Lengths differ");
+        assert!(matches!(
+            err.kind,
+            FastqErrorKind::LengthMismatch { seq: 4, qual: 3 }
+        ));
+        assert_eq!(err.record, 1);
+        assert_eq!(err.line, 8);
+        assert_eq!(err.offset, 27);
+    }
+
+    #[test]
+    fn test_parse_bad_header_and_separator() {
+        let err = parse_all(b">r1\nACGT\n+\nIIII\n").expect_err("FASTA header");
+        assert!(matches!(
+            err.kind,
+            FastqErrorKind::MissingHeader { found: b'>' }
+        ));
+        assert_eq!(err.line, 1);
+
+        let err = parse_all(b"@r1\nACGT\n-\nIIII\n").expect_err("Bad separator");
+        assert!(matches!(
+            err.kind,
+            FastqErrorKind::MissingSeparator { found: b'-' }
+        ));
+
+        let err = parse_all(b"@r1 x\nACGT\n+r2\nIIII\n").expect_err("Name mismatch");
+        assert!(matches!(err.kind, FastqErrorKind::NameMismatch { .. }));
+    }
+
+    #[test]
+    fn test_parse_invalid_characters() {
+        let err = parse_all(b"@r1\nAC1T\n+\nIIII\n").expect_err("Digit in sequence");
+        assert!(matches!(
+            err.kind,
+            FastqErrorKind::InvalidBase {
+                base: b'1',
+                column: 3
+            }
+        ));
+
+        let err = parse_all(b"@r1\nACGT\n+\nII I\n").expect_err("Space in quality");
+        assert!(matches!(
+            err.kind,
+            FastqErrorKind::InvalidQuality {
+                qual: b' ',
+                column: 3
+            }
+        ));
+    }
+
+    #[test]
+    fn test_parse_truncated_record() {
+        let err = parse_all(b"@r1\nACGT\n+\nIIII\n@r2\nACGT\n").expect_err("Truncated");
+        assert!(matches!(err.kind, FastqErrorKind::Truncated));
+        assert_eq!(err.record, 1);
+        assert_eq!(err.line, 7);
+    }
+
+    #[test]
+    fn test_parse_name_and_comment() {
+        let data = b"@A00417:578:H2GMJDSX3:1:1101:1072:1000 1:N:0:AGTCAAGA+ATAAGGCG\nACGT\n+\nFFFF\n@r2\tno\t comment\nA\n+r2\nF\n@r3\nA\n+\nF\n";
+        let records = parse_all(data).expect("Valid FASTQ");
+        assert_eq!(records[0].name, b"A00417:578:H2GMJDSX3:1:1101:1072:1000");
+        assert_eq!(records[0].comment, b"1:N:0:AGTCAAGA+ATAAGGCG");
+        assert_eq!(records[1].name, b"r2");
+        assert_eq!(records[1].comment, b"no\t comment");
+        assert_eq!(records[2].name, b"r3");
+        assert!(records[2].comment.is_empty());
+
+        let header = ReadHeader::parse(&records[0]).expect("Casava 1.8 header");
+        assert_eq!(header.format, HeaderFormat::Casava18);
+        assert_eq!(header.instrument.as_deref(), Some("A00417"));
+        assert_eq!(header.flowcell_id.as_deref(), Some("H2GMJDSX3"));
+        assert_eq!((header.lane, header.tile), (Some(1), Some(1101)));
+        assert_eq!(
+            (header.x, header.y, header.read),
+            (Some(1072), Some(1000), Some(1))
+        );
+        assert_eq!(header.index.as_deref(), Some("AGTCAAGA+ATAAGGCG"));
+    }
+
+    const RECORD: &[u8] = b"@r1\nACGT\n+\nIIII\n";
+
+    // Decompress `data` and return the detected format and the plain content.
+    fn roundtrip(data: Vec<u8>) -> (Compression, Vec<u8>) {
+        let (compression, mut reader) = decompress(std::io::Cursor::new(data)).expect("Readable");
+        let mut plain = Vec::new();
+        reader.read_to_end(&mut plain).expect("Decompressable");
+        (compression, plain)
+    }
+
+    #[test]
+    fn test_detect_plain_and_multi_member_gzip() {
+        assert_eq!(
+            roundtrip(RECORD.to_vec()),
+            (Compression::Plain, RECORD.to_vec())
+        );
+
+        // Two concatenated gzip members, e.g. `cat L001.fq.gz L002.fq.gz`
+        let mut data = Vec::new();
+        for _ in 0..2 {
+            let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+            enc.write_all(RECORD).unwrap();
+            data.extend(enc.finish().unwrap());
+        }
+        assert_eq!(roundtrip(data), (Compression::Gzip, RECORD.repeat(2)));
+    }
+
+    #[test]
+    fn test_detect_other_compressions() {
+        let mut enc = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::fast());
+        enc.write_all(RECORD).unwrap();
+        assert_eq!(
+            roundtrip(enc.finish().unwrap()),
+            (Compression::Bzip2, RECORD.to_vec())
+        );
+
+        let mut enc = xz2::write::XzEncoder::new(Vec::new(), 1);
+        enc.write_all(RECORD).unwrap();
+        assert_eq!(
+            roundtrip(enc.finish().unwrap()),
+            (Compression::Xz, RECORD.to_vec())
+        );
+
+        let data = zstd::encode_all(RECORD, 1).unwrap();
+        assert_eq!(roundtrip(data), (Compression::Zstd, RECORD.to_vec()));
+
+        // Minimal BGZF header: gzip with FEXTRA and a `BC` subfield
+        let bgzf = [
+            0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0,
+        ];
+        assert_eq!(Compression::detect(&bgzf), Compression::Bgzf);
+    }
+
+    #[test]
+    fn test_process_summary_and_errors() {
+        let mut runner = WorkflowRunner { statistics: vec![] };
+        let summary = runner
+            .process(RECORD.repeat(3).as_slice())
+            .expect("Valid FASTQ");
+        assert_eq!(
+            summary,
+            RunSummary {
+                records: 3,
+                bases: 12,
+                encoding: Some(QualityEncoding::Phred33),
+            }
+        );
+
+        // Plain text cut off inside the quality line
+        let data = RECORD.repeat(2);
+        let err = runner
+            .process(&data[..data.len() - 3])
+            .expect_err("Cut off");
+        assert!(matches!(err, FastqScanError::Truncated(_)));
+        assert_eq!(err.exit_code(), 5);
+
+        let err = runner
+            .process(&b"@r1\nACGT\n+\nIII\n"[..])
+            .expect_err("Bad record");
+        assert!(matches!(err, FastqScanError::Format(_)));
+        assert_eq!(err.exit_code(), 4);
+
+        let err = FastqScanError::Write {
+            path: PathBuf::from("output1.json"),
+            source: io::Error::from(io::ErrorKind::PermissionDenied),
+        };
+        assert_eq!(err.exit_code(), 3);
+    }
+
+    #[test]
+    fn test_process_truncated_and_corrupt_gzip() {
+        let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        for i in 0..1000 {
+            write!(enc, "@r{}\nACGTTGCA\n+\nIIII#III\n", i).unwrap();
+        }
+        let data = enc.finish().unwrap();
+        let mut runner = WorkflowRunner { statistics: vec![] };
+
+        let (_, reader) =
+            decompress(std::io::Cursor::new(data[..data.len() / 2].to_vec())).unwrap();
+        let err = runner.process(reader).expect_err("Truncated gzip");
+        assert!(matches!(err, FastqScanError::Truncated(_)), "{}", err);
+
+        // Flip a bit in the CRC32 of the trailer
+        let mut corrupt = data.clone();
+        let crc = corrupt.len() - 8;
+        corrupt[crc] ^= 0x01;
+        let (_, reader) = decompress(std::io::Cursor::new(corrupt)).unwrap();
+        let err = runner.process(reader).expect_err("CRC mismatch");
+        assert!(matches!(err, FastqScanError::Decompression(_)), "{}", err);
+        assert_eq!(err.exit_code(), 6);
+    }
+
+    fn all_statistics() -> Vec<Box<dyn Statistic>> {
+        vec![
+            Box::new(BaseCountPerPos::new()),
+            Box::new(BaseCountRead::new()),
+            Box::new(PhredPerPos::new()),
+            Box::new(PhredPerRead::new()),
+            Box::new(PhredPerSeq::new()),
+            Box::new(ReadData::new()),
+            Box::new(Duplication::new()),
+            Box::new(Overrepresented::new()),
+            Box::new(AdapterContent::new()),
+            Box::new(KmerContent::new()),
+            Box::new(PerTileQuality::new()),
+            Box::new(ByLane::new(vec![
+                Box::new(ReadData::new()),
+                Box::new(PhredPerPos::new()),
+            ])),
+        ]
+    }
+
+    // Records of varying length and content for merge tests.
+    fn mixed_records(n: usize) -> Vec<u8> {
+        let mut data = Vec::new();
+        for i in 0..n {
+            let len = 5 + i % 7;
+            let seq: String = (0..len)
+                .map(|j| b"ACGTN"[(i * 3 + j) % 5] as char)
+                .collect();
+            let qual: String = (0..len)
+                .map(|j| (b'#' + ((i + j) % 40) as u8) as char)
+                .collect();
+            writeln!(data, "@r{}\n{}\n+\n{}", i, seq, qual).unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn test_merge_equals_single_pass() {
+        let data = mixed_records(100);
+        let split = data.len() / 3;
+        let split = split + data[split..].windows(2).position(|w| w == b"\n@").unwrap() + 1;
+
+        let mut whole = WorkflowRunner {
+            statistics: all_statistics(),
+        };
+        whole.process(data.as_slice()).unwrap();
+
+        let mut first = WorkflowRunner {
+            statistics: all_statistics(),
+        };
+        first.process(&data[..split]).unwrap();
+        let mut second = WorkflowRunner {
+            statistics: all_statistics(),
+        };
+        second.process(&data[split..]).unwrap();
+        first.merge(&second);
+
+        assert_eq!(
+            serde_json::to_value(whole.finalize()).unwrap(),
+            serde_json::to_value(first.finalize()).unwrap()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot merge")]
+    fn test_merge_different_statistics() {
+        let mut stat = PhredPerPos::new();
+        stat.merge(&ReadData::new());
+    }
+
+    #[test]
+    fn test_parallel_equals_single_thread() {
+        let data = mixed_records(10_000);
+
+        let mut single = WorkflowRunner {
+            statistics: all_statistics(),
+        };
+        let expected = single.process(data.as_slice()).unwrap();
+
+        let mut parallel = WorkflowRunner {
+            statistics: all_statistics(),
+        };
+        let summary = parallel.process_parallel(data.as_slice(), 4).unwrap();
+
+        assert_eq!(expected, summary);
+        assert_eq!(
+            serde_json::to_value(single.finalize()).unwrap(),
+            serde_json::to_value(parallel.finalize()).unwrap()
+        );
+
+        // Errors are reported the same way as with a single thread
+        let err = parallel_error(&data[..data.len() - 2]);
+        assert!(matches!(err, FastqScanError::Truncated(_)), "{}", err);
+    }
+
+    #[test]
+    fn test_ordered_statistics() {
+        // Only these are merged chunk by chunk in input order
+        assert!(Duplication::new().ordered());
+        assert!(PhredPerRead::new().ordered());
+        assert!(!PhredPerRead::with_trace_len(0).ordered());
+        assert!(!ReadData::new().ordered());
+        assert!(!KmerContent::new().ordered());
+
+        let template: Vec<Box<dyn Statistic>> = vec![Box::new(Duplication::new())];
+        assert!(ByLane::new(template).ordered());
+        let template: Vec<Box<dyn Statistic>> = vec![Box::new(ReadData::new())];
+        assert!(!ByLane::new(template).ordered());
+    }
+
+    fn parallel_error(data: &[u8]) -> FastqScanError {
+        let mut runner = WorkflowRunner {
+            statistics: all_statistics(),
+        };
+        runner.process_parallel(data, 3).expect_err("Invalid input")
+    }
+
+    #[test]
+    fn test_report_and_tsv_output() {
+        let mut runner = WorkflowRunner {
+            statistics: vec![Box::new(BaseCountPerPos::new()), Box::new(ReadData::new())],
+        };
+        runner
+            .process(&b"@r1\nAC\n+\nII\n@r2\nGCA\n+\nIII\n@r3\nGG\n+\nII\n"[..])
+            .unwrap();
+        let reports: Vec<_> = runner.finalize().iter().map(|s| s.report()).collect();
+
+        let composition = &reports[0].tables[0];
+        assert_eq!(composition.columns[6], "GC%");
+        assert_eq!(composition.rows.len(), 3);
+        // Position 2: C, C and G
+        assert_eq!(composition.rows[1][6], Value::Float(100.0));
+        assert_eq!(reports[0].series[0].y, vec![2.0 / 3.0 * 100.0, 100.0, 0.0]);
+
+        let mut tsv = Vec::new();
+        write_tsv(&mut tsv, &reports[1..]).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert!(tsv.starts_with("# ReadData\nmetric\tvalue\nReads\t3\n"));
+        assert!(tsv.contains("# ReadData: Read lengths\nLength\tCount\n2\t2\n3\t1\n\n"));
+    }
+
+    #[test]
+    fn test_derived_metrics() {
+        let mut runner = WorkflowRunner {
+            statistics: vec![Box::new(ReadData::new()), Box::new(PhredPerPos::new())],
+        };
+        runner
+            .process(
+                &b"@r1\nACGT\n+\n+++5\n@r2\nAC\n+\n+5\n@r3\nACGTAC\n+\n555555\n@r4\nACG\n+\n+++\n"
+                    [..],
+            )
+            .unwrap();
+        let reports: Vec<_> = runner.finalize().iter().map(|s| s.report()).collect();
+
+        let metric = |report: usize, name: &str| {
+            reports[report]
+                .metrics
+                .iter()
+                .find(|m| m.name == name)
+                .map(|m| m.value.clone())
+                .unwrap()
+        };
+        assert_eq!(metric(0, "Min length"), Value::Int(2));
+        assert_eq!(metric(0, "Max length"), Value::Int(6));
+        assert_eq!(metric(0, "Mean length"), Value::Float(3.75));
+        assert_eq!(metric(0, "Median length"), Value::Float(3.5));
+        // 7 bases of Q10 and 8 bases of Q20
+        assert_eq!(metric(1, "Mean quality"), Value::Float(230.0 / 15.0));
+        assert!(reports[0].raw.is_none());
+    }
+
+    #[test]
+    fn test_histogram_summary_and_merge() {
+        let mut first = Histogram::new(0.0, 1.0, 101);
+        let mut second = first.clone();
+        for value in [10.0, 20.5, 20.9, 30.0] {
+            first.add(value);
+        }
+        for value in [40.0, 150.0] {
+            second.add(value);
+        }
+        first.merge(&second);
+
+        assert_eq!(first.total(), 6);
+        assert_eq!(first.min(), Some(10.0));
+        assert_eq!(first.max(), Some(150.0));
+        assert!((first.mean().unwrap() - 271.4 / 6.0).abs() < 1e-9);
+        // 3rd of 6 values lies in bin [20, 21)
+        assert_eq!(first.median(), Some(20.5));
+        assert_eq!(first.quantile(0.0), Some(10.5));
+        // Values beyond the last bin are counted there
+        assert_eq!(first.quantile(1.0), Some(100.5));
+        assert_eq!(Histogram::new(0.0, 1.0, 10).mean(), None);
+    }
+
+    #[test]
+    fn test_expected_errors_of_long_reads() {
+        // Q0 bases have one expected error each, the Q10 read is 10 kb long
+        let reads = [("!", 300), ("!", 400), ("!", 1000), ("+", 10_000)];
+        let mut stat = PhredPerRead::new();
+        for (qual, len) in reads {
+            stat.process(&FastqRecord {
+                seq: vec![b'A'; len],
+                qual: qual.repeat(len).into_bytes(),
+                ..Default::default()
+            });
+        }
+        let report = stat.report();
+        let metric = |name: &str| match report.metrics.iter().find(|m| m.name == name) {
+            Some(Metric {
+                value: Value::Float(value),
+                ..
+            }) => *value,
+            other => panic!("{}: {:?}", name, other),
+        };
+        // Lower median of 300, 400, 1000 and 1000, within the width of a bin
+        let median = metric("Median expected errors");
+        assert!((median - 400.0).abs() < 400.0 * 0.012, "got {}", median);
+        assert!((metric("Max expected errors") - 1000.0).abs() < 1e-6);
+        assert!((metric("Mean expected errors") - 675.0).abs() < 1e-6);
+
+        let mut errors = Histogram::log(0.001, 100, 9);
+        for value in [0.0, 0.0005, 0.05, 2500.0, 2e6] {
+            errors.add(value);
+        }
+        let median = errors.median().unwrap();
+        assert!((median - 0.05).abs() < 0.05 * 0.012, "got {}", median);
+        // Values beyond either end are counted in the first and last bin
+        assert!(errors.quantile(0.0).unwrap() < 0.0011);
+        assert!(errors.quantile(1.0).unwrap() < 1e6);
+        assert_eq!(errors.max(), Some(2e6));
+    }
+
+    #[test]
+    fn test_phred_per_read_trace_is_bounded() {
+        let mut runner = WorkflowRunner {
+            statistics: vec![Box::new(PhredPerRead::with_trace_len(50))],
+        };
+        runner.process(mixed_records(200).as_slice()).unwrap();
+        let report = runner.finalize()[0].report();
+
+        assert_eq!(report.metrics[0].value, Value::Int(200));
+        assert_eq!(report.series[1].y.len(), 50);
+        // Sampled from the whole input, in read order
+        let reads = &report.series[1].x;
+        assert!(reads.windows(2).all(|w| w[0] < w[1]));
+        assert!(reads[0] <= 50.0 && reads[49] > 150.0, "{:?}", reads);
+
+        // The same sample when the reads are split over threads
+        let data = mixed_records(20_000);
+        let new = || WorkflowRunner {
+            statistics: vec![Box::new(PhredPerRead::with_trace_len(50))],
+        };
+        let (mut single, mut parallel) = (new(), new());
+        single.process(data.as_slice()).unwrap();
+        parallel.process_parallel(data.as_slice(), 4).unwrap();
+        let (single, parallel) = (single.finalize(), parallel.finalize());
+        assert_eq!(single[0].report(), parallel[0].report());
+        assert!(single[0].report().series[1].x[49] > 15_000.0);
+    }
+
+    #[test]
+    fn test_quality_percentiles_per_position() {
+        let mut stat = PhredPerPos::new();
+        // Position 1 sees Q0..=Q9 once each, position 2 only Q40
+        for phred in 0..10u8 {
+            stat.process(&FastqRecord {
+                seq: b"AC".to_vec(),
+                qual: vec![phred + 33, b'I'],
+                ..Default::default()
+            });
+        }
+
+        assert_eq!(stat.mean(0), Some(4.5));
+        assert_eq!(stat.quantile(0, 0.1), Some(0));
+        assert_eq!(stat.quantile(0, 0.25), Some(2));
+        assert_eq!(stat.quantile(0, 0.5), Some(4));
+        assert_eq!(stat.quantile(0, 0.9), Some(8));
+        assert_eq!(stat.quantile(1, 0.1), Some(40));
+        assert_eq!(stat.quantile(2, 0.5), None);
+
+        let table = &stat.report().tables[0];
+        assert_eq!(
+            table.columns,
+            ["Pos", "Average Quality", "P10", "Q1", "Median", "Q3", "P90"]
+        );
+        assert_eq!(table.rows[0][5], Value::Int(7));
+    }
+
+    #[test]
+    fn test_per_sequence_quality_distribution() {
+        let data = b"@r1\nAC\n+\nII\n@r2\nACG\n+\n5+5\n@r3\nAC\n+\n5?\n@r4\nAC\n+\nII\n";
+        let mut first = PhredPerSeq::with_cutoffs(&[20, 30, 41]);
+        let mut second = PhredPerSeq::with_cutoffs(&[20, 30, 41]);
+        for (i, record) in parse_all(data).unwrap().iter().enumerate() {
+            if i < 2 { &mut first } else { &mut second }.process(record);
+        }
+        first.merge(&second);
+
+        // Means 40, 16.67 (rounded down to 16), 25 and 40
+        assert_eq!(first.total(), 4);
+        assert_eq!(first.mode(), Some(40));
+        assert_eq!(first.fraction_below(20), Some(0.25));
+        assert_eq!(first.fraction_below(30), Some(0.5));
+
+        let report = first.report();
+        assert_eq!(report.metrics[3].name, "Reads below Q30 (%)");
+        assert_eq!(report.metrics[4].value, Value::Float(100.0));
+        assert_eq!(report.tables[0].rows.len(), 40 - 16 + 1);
+        assert_eq!(
+            report.tables[0].rows[0],
+            vec![Value::Int(16), Value::Int(1)]
+        );
+        assert_eq!(PhredPerSeq::new().mode(), None);
+    }
+
+    fn records(seqs: &[&str]) -> Vec<FastqRecord> {
+        seqs.iter()
+            .map(|seq| FastqRecord {
+                seq: seq.as_bytes().to_vec(),
+                qual: vec![b'I'; seq.len()],
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_duplication_levels() {
+        let mut seqs = vec!["AAAA", "CCCC", "CCCC", "GGGG", "GGGG"];
+        seqs.extend(["TTTT"; 10]);
+        let mut stat = Duplication::new();
+        for record in records(&seqs) {
+            stat.process(&record);
+        }
+
+        let (dedup, total, remaining) = stat.levels();
+        assert_eq!(dedup[..3], [25.0, 50.0, 0.0]);
+        assert_eq!(total[0], 100.0 / 15.0);
+        // TTTT occurs 10 times
+        assert!((total[9] - 1000.0 / 15.0).abs() < 1e-9);
+        assert_eq!(remaining, 4.0 / 15.0 * 100.0);
+
+        // Long reads are compared by their first 50 bases only
+        let mut stat = Duplication::new();
+        let long = format!("{}{}", "A".repeat(50), "C".repeat(30));
+        let other = format!("{}{}", "A".repeat(50), "G".repeat(30));
+        for record in records(&[&long, &other]) {
+            stat.process(&record);
+        }
+        assert_eq!(stat.levels().2, 50.0);
+    }
+
+    #[test]
+    fn test_duplication_limit_and_merge() {
+        let seqs = ["A", "C", "A", "C", "G", "T", "G", "T", "AC", "AC"];
+        let all = records(&seqs);
+
+        let mut whole = Duplication::with_limit(4);
+        for record in &all {
+            whole.process(record);
+        }
+        // AC was seen after the limit was reached
+        let report = whole.report();
+        assert_eq!(report.metrics[1].value, Value::Int(4));
+        assert!(whole.levels().2 > 40.0);
+
+        // Exact as long as the second part stays below the limit on its own,
+        // including when the limit is reached while merging
+        for split in 4..all.len() {
+            let mut first = Duplication::with_limit(4);
+            let mut second = Duplication::with_limit(4);
+            all[..split].iter().for_each(|r| first.process(r));
+            all[split..].iter().for_each(|r| second.process(r));
+            first.merge(&second);
+            assert_eq!(first.levels(), whole.levels(), "split at {}", split);
+        }
+    }
+
+    // FASTQ text of reads with the sequences `seqs`.
+    fn fastq(seqs: &[&str]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (i, seq) in seqs.iter().enumerate() {
+            writeln!(data, "@r{}\n{}\n+\n{}", i, seq, "I".repeat(seq.len())).unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn test_duplication_over_several_files() {
+        // Both files exceed the limit on their own
+        let (file1, file2) = (&["A", "C", "G"], &["G", "T", "A", "A"]);
+        let mut whole = WorkflowRunner {
+            statistics: vec![Box::new(Duplication::with_limit(2))],
+        };
+        whole
+            .process(fastq(&[&file1[..], &file2[..]].concat()).as_slice())
+            .unwrap();
+
+        // Files are scanned one after the other with the same runner
+        let mut files = WorkflowRunner {
+            statistics: vec![Box::new(Duplication::with_limit(2))],
+        };
+        files.process(fastq(file1).as_slice()).unwrap();
+        files.process(fastq(file2).as_slice()).unwrap();
+
+        let (whole, files) = (whole.finalize(), files.finalize());
+        let levels =
+            |stats: &[Box<dyn Statistic>]| downcast::<Duplication>(stats[0].as_ref()).levels();
+        assert_eq!(levels(&files), levels(&whole));
+        assert_eq!(files[0].report(), whole[0].report());
+    }
+
+    #[test]
+    fn test_overrepresented_sequences() {
+        // Adapter dimer made of the start of the TruSeq index adapter
+        let dimer = "GATCGGAAGAGCACACGTCTGAACTCCAGTCAC";
+        let dimer_rc = String::from_utf8(reverse_complement(dimer.as_bytes())).unwrap();
+        let mut seqs = vec!["ACGTTGCAACGTTGCA"; 3];
+        seqs.extend(vec![dimer; 5]);
+        seqs.extend(vec![dimer_rc.as_str(); 2]);
+        seqs.extend(["CCCCCCCCCCCCCC", "AAAAAAAAAACCCCCCCCCC"]);
+
+        let mut first = Overrepresented::with_threshold(0.1)
+            .with_contaminants(vec![Contaminant::new("Spike-in", b"acgttgcaacgttgca")]);
+        let mut second = first.empty();
+        let all = records(&seqs);
+        all[..6].iter().for_each(|r| first.process(r));
+        all[6..].iter().for_each(|r| second.process(r));
+        first.merge(second.as_ref());
+
+        // Sequences seen more than once in 12 reads
+        assert_eq!(
+            first.sequences(),
+            [(dimer, 5), ("ACGTTGCAACGTTGCA", 3), (dimer_rc.as_str(), 2)]
+        );
+        let (source, len) = first.source(dimer).unwrap();
+        assert_eq!((source.name.as_str(), len), ("TruSeq Adapter, Index 1", 33));
+        assert_eq!(first.source(&dimer_rc).unwrap().1, 33);
+        assert_eq!(first.source("ACGTTGCAACGTTGCA").unwrap().0.name, "Spike-in");
+        assert!(first.source("AAAAAAAAAACCCCCCCCCC").is_none());
+
+        let table = &first.report().tables[0];
+        assert_eq!(table.rows[0][2], Value::Float(5.0 / 12.0 * 100.0));
+        assert_eq!(
+            table.rows[0][3],
+            Value::Text("TruSeq Adapter, Index 1 (33 bp match)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_overrepresented_over_several_files() {
+        // Both files exceed the limit of two sequences, ACGT is tracked from
+        // the first file and occurs three more times in the second
+        let file1 = ["ACGT", "CCCC", "GGGG"];
+        let file2 = ["GGGG", "TTTT", "ACGT", "ACGT", "ACGT"];
+        let new = || WorkflowRunner {
+            statistics: vec![Box::new(Overrepresented::with_threshold(0.2).with_limit(2))],
+        };
+        let mut whole = new();
+        whole
+            .process(fastq(&[&file1[..], &file2[..]].concat()).as_slice())
+            .unwrap();
+        let mut files = new();
+        files.process(fastq(&file1).as_slice()).unwrap();
+        files.process(fastq(&file2).as_slice()).unwrap();
+
+        let (whole, files) = (whole.finalize(), files.finalize());
+        let overrepresented = downcast::<Overrepresented>(files[0].as_ref());
+        assert_eq!(overrepresented.sequences(), vec![("ACGT", 4)]);
+        assert_eq!(files[0].report(), whole[0].report());
+    }
+
+    #[test]
+    fn test_read_fasta() {
+        let path = std::env::temp_dir().join(format!("fastq-scan-{}.fa", std::process::id()));
+        std::fs::write(&path, b">first adapter\nACGT\nacgt\n\n>second\nTTTT\n").unwrap();
+        let entries = read_fasta(&path).unwrap();
+        std::fs::write(&path, b"ACGT\n").unwrap();
+        let err = read_fasta(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            entries,
+            [
+                ("first adapter".to_string(), b"ACGTACGT".to_vec()),
+                ("second".to_string(), b"TTTT".to_vec())
+            ]
+        );
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_adapter_content() {
+        let adapters = vec![
+            Adapter::new("Universal", b"AGATCGGAAGAG"),
+            "Custom=ttttcccc".parse().unwrap(),
+        ];
+        let mut stat = AdapterContent::with_adapters(adapters);
+        for record in records(&[
+            "AGATCGGAAGAGCC",
+            "CCAGATCGGAAGAG",
+            "TTTTCCCCAGATCGG",
+            "ACGT",
+        ]) {
+            stat.process(&record);
+        }
+
+        let percentages = stat.percentages();
+        assert_eq!(percentages[0].len(), 15);
+        assert_eq!(percentages[0][..3], [25.0, 25.0, 50.0]);
+        assert_eq!(percentages[0][14], 50.0);
+        assert_eq!(percentages[1][0], 25.0);
+        assert_eq!(stat.adapters()[1].seq, b"TTTTCCCC");
+
+        let report = stat.report();
+        assert_eq!(report.tables[0].columns, ["Pos", "Universal", "Custom"]);
+        assert_eq!(report.series.len(), 2);
+        assert!("Custom".parse::<Adapter>().is_err());
+        assert!("Custom=ACGU".parse::<Adapter>().is_err());
+
+        // FASTA entries are checked the same way
+        assert!(Adapter::parse("Empty", b"").is_err());
+        assert!(Adapter::parse("Custom", b"ACGU").is_err());
+        assert_eq!(
+            Adapter::parse("Custom", b"acgt"),
+            Ok(Adapter::new("Custom", b"ACGT"))
+        );
+
+        // Lower case reads match, empty adapters never
+        let mut stat = AdapterContent::with_adapters(vec![
+            Adapter::new("Universal", b"AGATCGGAAGAG"),
+            Adapter::new("Empty", b""),
+        ]);
+        stat.process(&records(&["ccagatcggaagag"])[0]);
+        assert_eq!(stat.percentages()[0][2], 100.0);
+        assert_eq!(stat.percentages()[1][13], 0.0);
+    }
+
+    // Pseudo-random reads of length `len`, the same for every run.
+    fn random_seqs(n: usize, len: usize) -> Vec<String> {
+        let mut state: u64 = 42;
+        (0..n)
+            .map(|_| {
+                (0..len)
+                    .map(|_| {
+                        state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                        b"ACGT"[(state >> 62) as usize] as char
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_kmer_enrichment() {
+        let mut seqs = random_seqs(400, 60);
+        // A ligation artefact at the start of every fourth read
+        for seq in seqs.iter_mut().step_by(4) {
+            seq.replace_range(..7, "GGGCCCA");
+        }
+
+        let mut first = KmerContent::new();
+        let mut second = KmerContent::new();
+        for (i, record) in records(&seqs.iter().map(String::as_str).collect::<Vec<_>>())
+            .iter()
+            .enumerate()
+        {
+            if i < 150 { &mut first } else { &mut second }.process(record);
+        }
+        first.merge(&second);
+
+        let hits = first.hits();
+        assert_eq!(hits[0].kmer, "GGGCCCA");
+        assert_eq!(hits[0].peak, (1, 5));
+        assert!(hits[0].count >= 100);
+        assert!(hits[0].max_ratio > 5.0 && hits[0].p_value < 1e-10);
+        // Random k-mers are not enriched
+        assert!(hits.iter().all(|hit| hit.count >= 5));
+
+        let report = first.report();
+        assert_eq!(report.tables[0].rows[0][4], Value::Text("1-5".to_string()));
+        assert!(KmerContent::new().hits().is_empty());
+    }
+
+    #[test]
+    fn test_kmer_memory_limit() {
+        assert_eq!(KmerContent::new().max_positions(), 5120);
+        let longest = KmerContent::with_kmer_size(kmer_content::MAX_KMER_SIZE);
+        assert_eq!(longest.max_positions(), 80);
+        assert!(std::panic::catch_unwind(|| KmerContent::with_kmer_size(11)).is_err());
+
+        // K-mers of a long read are only counted in the first windows
+        let mut stat = longest;
+        for record in records(&[&random_seqs(1, 10_000)[0]]) {
+            stat.process(&record);
+        }
+        let raw = serde_json::to_value(&stat).unwrap();
+        assert_eq!(raw["window_totals"].as_array().unwrap().len(), 16);
+        assert_eq!(raw["window_totals"][0], 5);
+        let counts = raw["counts"].as_object().unwrap();
+        assert!(counts.values().all(|c| c.as_array().unwrap().len() <= 16));
+    }
+
+    #[test]
+    fn test_per_tile_quality() {
+        let mut data = Vec::new();
+        for (tile, qual) in [
+            (1101, "IIII"),
+            (1101, "IIII"),
+            (1102, "IIII"),
+            (1102, "II55"),
+        ] {
+            writeln!(
+                data,
+                "@M1:7:FC1:1:{}:10:20 1:N:0:ACGT\nACGT\n+\n{}",
+                tile, qual
+            )
+            .unwrap();
+        }
+        data.extend(b"@SRR1.1\nACGT\n+\nIIII\n");
+
+        let mut stat = PerTileQuality::with_threshold(4.0);
+        for record in parse_all(&data).unwrap() {
+            stat.process(&record);
+        }
+
+        // Position 3: mean of all tiles 35, tile 1102 has 30
+        let deviations = stat.deviations();
+        assert_eq!(deviations.len(), 2);
+        assert_eq!((deviations[0].tile, deviations[0].deviation), (1101, 5.0));
+        assert_eq!((deviations[1].position, deviations[1].deviation), (3, -5.0));
+        assert_eq!(stat.deviating_tiles().len(), 2);
+        assert!(
+            PerTileQuality::with_threshold(5.0)
+                .deviating_tiles()
+                .is_empty()
+        );
+
+        let report = stat.report();
+        assert_eq!(report.metrics[1].value, Value::Int(1));
+        assert_eq!(report.tables[0].rows[1][0], Value::Int(1102));
+    }
+
+    #[test]
+    fn test_statistics_by_lane() {
+        let mut data = Vec::new();
+        for (flowcell, lane, seq) in [
+            ("FC2", 1, "ACGT"),
+            ("FC1", 2, "AC"),
+            ("FC1", 1, "ACG"),
+            ("FC2", 1, "ACGTA"),
+        ] {
+            let qual = "I".repeat(seq.len());
+            writeln!(
+                data,
+                "@M1:7:{}:{}:1101:10:20 1:N:0:1\n{}\n+\n{}",
+                flowcell, lane, seq, qual
+            )
+            .unwrap();
+        }
+        data.extend(b"@SRR1.1\nACGTACGT\n+\nIIIIIIII\n");
+
+        let template: Vec<Box<dyn Statistic>> = vec![Box::new(ReadData::new())];
+        let mut runner = WorkflowRunner {
+            statistics: vec![Box::new(ByLane::new(template))],
+        };
+        runner.process_parallel(data.as_slice(), 2).unwrap();
+        let stats = runner.finalize();
+        let reports = stats[0].reports();
+
+        // Overview plus one ReadData report per group
+        assert_eq!(reports.len(), 5);
+        assert_eq!(
+            reports[0].tables[0].rows[0][0],
+            Value::Text("unknown".to_string())
+        );
+        assert_eq!(reports[0].tables[0].rows[3][2], Value::Int(2));
+        let groups: Vec<_> = reports[1..].iter().map(|r| r.title()).collect();
+        assert_eq!(
+            groups,
+            [
+                "ReadData [unknown lane]",
+                "ReadData [FC1 lane 1]",
+                "ReadData [FC1 lane 2]",
+                "ReadData [FC2 lane 1]"
+            ]
+        );
+        assert_eq!(reports[4].metrics[3].value, Value::Float(4.5));
+    }
+
+    #[test]
+    fn test_sample_metadata_from_file_name() {
+        let illumina =
+            SampleMetadata::from_path(Path::new("/runs/x/Tumor_1_S3_L002_R2_001.fastq.gz"));
+        assert_eq!(
+            illumina,
+            SampleMetadata {
+                file_name: "Tumor_1_S3_L002_R2_001.fastq.gz".to_string(),
+                sample_name: "Tumor_1".to_string(),
+                sample_number: Some(3),
+                barcode: None,
+                lane: Some(2),
+                read: Some(2),
+                index_read: false,
+                set_number: Some(1),
+            }
+        );
+
+        let barcode = SampleMetadata::from_path(Path::new("Sample_ACGTACGT_L001_I1_001.fq"));
+        assert_eq!(barcode.barcode.as_deref(), Some("ACGTACGT"));
+        assert_eq!((barcode.read, barcode.index_read), (Some(1), true));
+
+        // Fallbacks for other names
+        let sra = SampleMetadata::from_path(Path::new("SRR1234567_1.fastq.bz2"));
+        assert_eq!(
+            (sra.sample_name.as_str(), sra.read, sra.lane),
+            ("SRR1234567", Some(1), None)
+        );
+        let plain = SampleMetadata::from_path(Path::new("reads.fastq"));
+        assert_eq!((plain.sample_name.as_str(), plain.read), ("reads", None));
+        assert_eq!(
+            SampleMetadata::from_path(Path::new("-")).sample_name,
+            "stdin"
+        );
+
+        let report = Report::new("Summary").with_samples(vec![illumina]);
+        let mut tsv = Vec::new();
+        write_tsv(&mut tsv, std::slice::from_ref(&report)).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert!(tsv.contains("Tumor_1_S3_L002_R2_001.fastq.gz\tTumor_1\t3\t-\t2\t2\t1\n"));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["samples"][0]["lane"], 2);
+    }
+
+    #[test]
+    fn test_detect_header_formats() {
+        let data = b"@A00417:578:H2GMJDSX3:1:1101:1072:1000 1:Y:0:AGTC
+A
++
+F
+\
+@HWUSI-EAS100R:6:73:941:1973#0/1
+A
++
+F
+\
+@V300012345L2C003R0040000123/2
+A
++
+F
+\
+@m54006_160504_020705/4194370/ccs
+A
++
+F
+\
+@m54006_160504_020705/4194371/100_250
+A
++
+F
+\
+@2c9a5ad1-9a83-4a8e-8d8b-2a3e8b1f2c44 runid=abc123 ch=42 flow_cell_id=FAO12345
+A
++
+F
+\
+@SRR001666.1 071112_SLXA-EAS1_s_7:5:1:817:345/1 length=36
+A
++
+F
+\
+@ERR123456.7.2
+A
++
+F
+\
+@inst.01:12:FC1:3:2104:10:20
+A
++
+F
+\
+@read_1
+A
++
+F
+";
+        let records = parse_all(data).expect("Valid FASTQ");
+        let headers: Vec<_> = records.iter().map(ReadHeader::parse).collect();
+        let formats: Vec<_> = headers
+            .iter()
+            .map(|h| h.as_ref().map(|h| h.format))
+            .collect();
+        assert_eq!(
+            formats,
+            [
+                Some(HeaderFormat::Casava18),
+                Some(HeaderFormat::IlluminaLegacy),
+                Some(HeaderFormat::Mgi),
+                Some(HeaderFormat::PacBio),
+                Some(HeaderFormat::PacBio),
+                Some(HeaderFormat::Ont),
+                Some(HeaderFormat::Sra),
+                Some(HeaderFormat::Sra),
+                Some(HeaderFormat::Casava18),
+                None,
+            ]
+        );
+        let header = |i: usize| headers[i].as_ref().unwrap();
+
+        assert_eq!(header(0).is_filtered, Some(true));
+        assert_eq!(header(0).index.as_deref(), Some("AGTC"));
+
+        let legacy = header(1);
+        assert_eq!(legacy.instrument.as_deref(), Some("HWUSI-EAS100R"));
+        assert_eq!((legacy.lane, legacy.tile), (Some(6), Some(73)));
+        assert_eq!((legacy.x, legacy.y), (Some(941), Some(1973)));
+        assert_eq!((legacy.index.as_deref(), legacy.read), (Some("0"), Some(1)));
+
+        let mgi = header(2);
+        assert_eq!(mgi.flowcell_id.as_deref(), Some("V300012345"));
+        assert_eq!((mgi.lane, mgi.tile), (Some(2), Some(3004)));
+        assert_eq!((mgi.spot, mgi.read), (Some(123), Some(2)));
+
+        let pacbio = header(3);
+        assert_eq!(pacbio.instrument.as_deref(), Some("54006"));
+        assert_eq!(pacbio.run.as_deref(), Some("m54006_160504_020705"));
+        assert_eq!(pacbio.spot, Some(4194370));
+        assert_eq!(header(4).attributes["subread"], "100_250");
+
+        let ont = header(5);
+        assert_eq!(ont.run.as_deref(), Some("abc123"));
+        assert_eq!(ont.flowcell_id.as_deref(), Some("FAO12345"));
+        assert_eq!(ont.attributes["ch"], "42");
+
+        // The original Illumina name of SRA reads is kept
+        let sra = header(6);
+        assert_eq!((sra.run.as_deref(), sra.spot), (Some("SRR001666"), Some(1)));
+        assert_eq!((sra.lane, sra.tile, sra.read), (Some(5), Some(1), Some(1)));
+        assert_eq!((header(7).spot, header(7).read), (Some(7), Some(2)));
+
+        // Dots in the instrument name
+        let dotted = header(8);
+        assert_eq!(dotted.instrument.as_deref(), Some("inst.01"));
+        assert_eq!((dotted.lane, dotted.tile), (Some(3), Some(2104)));
+        assert_eq!(dotted.read, None);
+
+        assert!(ReadHeader::parse_as(&records[1], HeaderFormat::Casava18).is_none());
+        // The format of the first read is kept, other formats are still parsed
+        let mut format = None;
+        for (record, expected) in records.iter().zip(&headers) {
+            assert_eq!(&ReadHeader::parse_detected(record, &mut format), expected);
+        }
+        assert_eq!(format, headers[0].as_ref().map(|h| h.format));
+
+        let mut tiles = PerTileQuality::new();
+        for record in &records {
+            tiles.process(record);
+        }
+        let tiles: Vec<_> = tiles.deviations().iter().map(|d| d.tile).collect();
+        assert_eq!(tiles, [1, 73, 1101, 2104, 3004]);
+    }
+
+    // Counts the pairs and their bases, to check which pairs are passed on.
+    #[derive(Default, Serialize, Deserialize)]
+    struct PairBases {
+        pairs: u64,
+        bases: u64,
+    }
+
+    #[typetag::serde]
+    impl PairedStatistic for PairBases {
+        fn process(&mut self, r1: &FastqRecord, r2: &FastqRecord) {
+            self.pairs += 1;
+            self.bases += (r1.seq.len() + r2.seq.len()) as u64;
+        }
+
+        fn report(&self) -> Report {
+            Report::new("PairBases").with_metric("Pairs", self.pairs)
+        }
+
+        fn merge(&mut self, other: &dyn PairedStatistic) {
+            let other = downcast_paired::<Self>(other);
+            self.pairs += other.pairs;
+            self.bases += other.bases;
+        }
+
+        fn empty(&self) -> Box<dyn PairedStatistic> {
+            Box::new(PairBases::default())
+        }
+    }
+
+    fn paired_runner() -> PairedRunner {
+        PairedRunner {
+            r1: all_statistics(),
+            r2: all_statistics(),
+            pairs: vec![Box::new(PairBases::default())],
+        }
+    }
+
+    fn paired_error(r1: &[u8], r2: &[u8]) -> FastqScanError {
+        let reader = PairedReader::new(r1, r2, MateCheck::Strict);
+        paired_runner()
+            .process_parallel(reader, 2)
+            .expect_err("Not paired")
+    }
+
+    #[test]
+    fn test_paired_reading() {
+        assert_eq!(mate_name(b"read7/1"), b"read7");
+        assert_eq!(mate_name(b"read7/2"), b"read7");
+        assert_eq!(mate_name(b"read7/3"), b"read7/3");
+
+        let r1 = mixed_records(10_000);
+        // Mate names with `/2` and a comment
+        let r2: String = String::from_utf8_lossy(&r1)
+            .lines()
+            .enumerate()
+            .map(|(i, line)| match i % 4 {
+                0 => format!("{}/2 2:N:0:ACGT\n", line),
+                2 => "+\n".to_string(),
+                _ => format!("{}\n", line),
+            })
+            .collect();
+
+        let mut single = paired_runner();
+        let summary = single
+            .process(PairedReader::new(
+                r1.as_slice(),
+                r2.as_bytes(),
+                MateCheck::Strict,
+            ))
+            .unwrap();
+        assert_eq!((summary.pairs, summary.r2.records), (10_000, 10_000));
+        assert_eq!(summary.unpaired(), (0, 0));
+
+        let mut parallel = paired_runner();
+        let reader = PairedReader::new(r1.as_slice(), r2.as_bytes(), MateCheck::Strict);
+        assert_eq!(parallel.process_parallel(reader, 4).unwrap(), summary);
+        assert_eq!(
+            serde_json::to_value(&single.pairs).unwrap(),
+            serde_json::to_value(&parallel.pairs).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(&single.r2).unwrap(),
+            serde_json::to_value(&parallel.r2).unwrap()
+        );
+
+        // Different names and record counts
+        let other = RECORD.repeat(3);
+        let err = paired_error(&r1, &other);
+        let FastqScanError::Pairing(e) = &err else {
+            panic!("{}", err)
+        };
+        assert!(
+            matches!(&e.kind, PairErrorKind::NameMismatch { r1, r2 } if r1 == "r0" && r2 == "r1")
+        );
+        assert_eq!(err.exit_code(), 7);
+        let err = paired_error(&RECORD.repeat(3), &RECORD.repeat(2));
+        let FastqScanError::Pairing(e) = &err else {
+            panic!("{}", err)
+        };
+        assert!(matches!(e.kind, PairErrorKind::MissingMate { mate: 2 }));
+        assert_eq!(e.pair, 2);
+
+        // Errors in one file name the mate
+        let err = paired_error(&RECORD.repeat(2), b"@r1\nACGT\n+\nIII\n");
+        assert!(
+            matches!(err, FastqScanError::Mate { mate: 2, .. }),
+            "{}",
+            err
+        );
+        assert_eq!(err.exit_code(), 4);
+
+        // Only counted with `MateCheck::Warn`, unpaired records go to the per-mate statistics
+        let r1 = b"@a/1\nAC\n+\nII\n@b/1\nAC\n+\nII\n@c/1\nAC\n+\nII\n";
+        let r2 = b"@a/2\nACG\n+\nIII\n@x/2\nACG\n+\nIII\n";
+        let mut runner = paired_runner();
+        let summary = runner
+            .process(PairedReader::new(&r1[..], &r2[..], MateCheck::Warn))
+            .unwrap();
+        assert_eq!((summary.pairs, summary.name_mismatches), (2, 1));
+        assert_eq!(summary.unpaired(), (1, 0));
+        assert_eq!(summary.r1.records, 3);
+        let pairs = downcast_paired::<PairBases>(runner.pairs[0].as_ref());
+        assert_eq!((pairs.pairs, pairs.bases), (2, 10));
+    }
+
+    // Every record of `r1` followed by a copy as its mate.
+    fn interleave(r1: &[u8]) -> Vec<u8> {
+        let text = String::from_utf8_lossy(r1);
+        let lines: Vec<&str> = text.lines().collect();
+        let mut interleaved = Vec::new();
+        for record in lines.chunks(4) {
+            for mate in 1..=2 {
+                writeln!(
+                    interleaved,
+                    "{}/{}\n{}\n+\n{}",
+                    record[0], mate, record[1], record[3]
+                )
+                .unwrap();
+            }
+        }
+        interleaved
+    }
+
+    #[test]
+    fn test_interleaved_input() {
+        let r1 = mixed_records(1000);
+        let interleaved = interleave(&r1);
+        let detect = |data: &[u8]| detect_interleaved(&mut FastqReader::new(data));
+        assert!(detect(&interleaved));
+        assert!(!detect(&r1));
+        assert!(!detect(&RECORD.repeat(4)));
+        assert!(!detect(b""));
+        // Errors among the records looked at are reported by the processing
+        let cut = String::from_utf8_lossy(&interleaved).find("@r2/2").unwrap() + 3;
+        let mut records = FastqReader::new(&interleaved[..cut]);
+        assert!(!detect_interleaved(&mut records));
+        let err = paired_runner()
+            .process(PairedReader::interleaved_records(
+                records,
+                MateCheck::Strict,
+            ))
+            .expect_err("Truncated input");
+        assert_eq!(err.exit_code(), 5, "{}", err);
+
+        // The same results as with two files
+        let mut files = paired_runner();
+        let expected = files
+            .process(PairedReader::new(
+                r1.as_slice(),
+                r1.as_slice(),
+                MateCheck::Strict,
+            ))
+            .unwrap();
+        // The records looked at for the detection are read again
+        let mut records = FastqReader::new(interleaved.as_slice());
+        assert!(detect_interleaved(&mut records));
+        let mut runner = paired_runner();
+        let reader = PairedReader::interleaved_records(records, MateCheck::Strict);
+        assert_eq!(runner.process_parallel(reader, 3).unwrap(), expected);
+        for (a, b) in [(&files.r1, &runner.r1), (&files.r2, &runner.r2)] {
+            assert_eq!(
+                serde_json::to_value(a).unwrap(),
+                serde_json::to_value(b).unwrap()
+            );
+        }
+
+        // An odd number of records leaves the last R1 without a mate
+        let last = String::from_utf8_lossy(&interleaved)
+            .rfind("@r999/2")
+            .unwrap();
+        let odd = &interleaved[..last];
+        let mut runner = paired_runner();
+        let err = runner
+            .process(PairedReader::interleaved(odd, MateCheck::Strict))
+            .expect_err("Odd number of records");
+        let FastqScanError::Pairing(e) = &err else {
+            panic!("{}", err)
+        };
+        assert!(matches!(e.kind, PairErrorKind::MissingMate { mate: 2 }));
+        assert_eq!(e.pair, 999);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_interleaved_detection_on_pipe() {
+        use std::os::fd::AsRawFd;
+
+        let interleaved = interleave(&mixed_records(100));
+        let (pipe, mut input) = io::pipe().unwrap();
+        let writer = std::thread::spawn(move || input.write_all(&interleaved));
+
+        // A path like `<(cat interleaved.fq)`, whose content can only be read once
+        let path = PathBuf::from(format!("/dev/fd/{}", pipe.as_raw_fd()));
+        let mut records = FastqReader::new(process_fastq(path).unwrap());
+        assert!(detect_interleaved(&mut records));
+        let mut runner = paired_runner();
+        let summary = runner
+            .process(PairedReader::interleaved_records(
+                records,
+                MateCheck::Strict,
+            ))
+            .unwrap();
+        assert_eq!((summary.pairs, summary.unpaired()), (100, (0, 0)));
+        writer.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_insert_size_from_overlap() {
+        const R1_ADAPTER: &str = "AGATCGGAAGAGCACACGTCTGAACTCCAGTCA";
+        const R2_ADAPTER: &str = "AGATCGGAAGAGCGTCGTGTAGGGAAAGAGTGT";
+        let seqs = random_seqs(8, 200);
+        // Reads of 100 bases from both ends of a fragment, continuing into the adapter
+        let pair = |fragment: &str| {
+            let rc = String::from_utf8(reverse_complement(fragment.as_bytes())).unwrap();
+            let r1 = format!("{}{}{}", fragment, R1_ADAPTER, seqs[7])[..100].to_string();
+            let r2 = format!("{}{}{}", rc, R2_ADAPTER, seqs[6])[..100].to_string();
+            (r1, r2)
+        };
+
+        let mut insert_size = InsertSize::new();
+        for (i, len) in [40, 80, 120, 150, 200].into_iter().enumerate() {
+            let (r1, mut r2) = pair(&seqs[i][..len]);
+            // A sequencing error in the overlap
+            r2.replace_range(35..36, if &r2[35..36] == "A" { "C" } else { "A" });
+            let expected = (len <= 170).then_some(len);
+            assert_eq!(
+                fragment_length(r1.as_bytes(), r2.as_bytes()),
+                expected,
+                "{}",
+                len
+            );
+            let records = records(&[&r1, &r2]);
+            insert_size.process(&records[0], &records[1]);
+        }
+        assert_eq!(fragment_length(b"ACGT", b"ACGT"), None);
+
+        assert_eq!(insert_size.overlapping(), 4);
+        assert_eq!(insert_size.read_through(), [2, 2]);
+        let lengths: Vec<_> = insert_size.fragment_lengths().keys().copied().collect();
+        assert_eq!(lengths, [40, 80, 120, 150]);
+
+        let report = insert_size.report();
+        let metric = |name: &str| {
+            report
+                .metrics
+                .iter()
+                .find(|m| m.name == name)
+                .map(|m| m.value.clone())
+        };
+        assert_eq!(metric("Overlapping pairs (%)"), Some(Value::Float(80.0)));
+        assert_eq!(metric("Mean fragment length"), Some(Value::Float(97.5)));
+        let adapters = &report.tables[1];
+        assert_eq!(adapters.rows.len(), 1);
+        assert_eq!(
+            adapters.rows[0],
+            vec![
+                Value::from("Illumina Universal Adapter"),
+                Value::from(2u64),
+                Value::from(2u64)
+            ]
+        );
+
+        let mut merged = InsertSize::new();
+        merged.merge(&insert_size);
+        merged.merge(&InsertSize::new());
+        assert_eq!(
+            serde_json::to_value(&merged).unwrap(),
+            serde_json::to_value(&insert_size).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_quality_encoding() {
+        assert_eq!(
+            QualityEncoding::detect(b'#', b'J'),
+            QualityEncoding::Phred33
+        );
+        // High quality Phred+33 is not mistaken for Phred+64
+        assert_eq!(
+            QualityEncoding::detect(b'F', b'J'),
+            QualityEncoding::Phred33
+        );
+        assert_eq!(
+            QualityEncoding::detect(b'B', b'h'),
+            QualityEncoding::Phred64
+        );
+        assert_eq!(QualityEncoding::detect(b';', b'h'), QualityEncoding::Solexa);
+        assert_eq!("solexa".parse(), Ok(QualityEncoding::Solexa));
+        assert!("65".parse::<QualityEncoding>().is_err());
+
+        let read = |data: &[u8], encoding| {
+            let mut reader = FastqReader::with_encoding(data, encoding);
+            let mut record = FastqRecord::default();
+            let mut quals = Vec::new();
+            loop {
+                match reader.read_record(&mut record) {
+                    Ok(true) => quals.push(String::from_utf8(record.qual.clone()).unwrap()),
+                    Ok(false) => return Ok((reader.encoding(), quals)),
+                    Err(e) => return Err(e),
+                }
+            }
+        };
+        let phred64 = b"@r1\nACGT\n+\nhB@h\n@r2\nA\n+\nT\n";
+        assert_eq!(
+            read(phred64, None).unwrap(),
+            (
+                Some(QualityEncoding::Phred64),
+                vec!["I#!I".into(), "5".into()]
+            )
+        );
+        // Solexa -5, 0, 10 and 40
+        let solexa = b"@r1\nACGT\n+\n;@Jh\n";
+        assert_eq!(
+            read(solexa, None).unwrap(),
+            (Some(QualityEncoding::Solexa), vec!["\"$+I".into()])
+        );
+        assert_eq!(read(RECORD, None).unwrap().1, vec!["IIII".to_string()]);
+
+        // Characters below the range of the encoding
+        let err = read(b"@r1\nAC\n+\nI5\n", Some(QualityEncoding::Phred64)).expect_err("Below '@'");
+        assert!(matches!(
+            err.kind,
+            FastqErrorKind::QualityOutOfRange {
+                qual: b'5',
+                column: 2,
+                encoding: QualityEncoding::Phred64
+            }
+        ));
+        assert_eq!(FastqScanError::from(err).exit_code(), 4);
+        let mut data = b"@r\nACGT\n+\nhhhh\n".repeat(10_001);
+        data.extend_from_slice(b"@bad\nACGT\n+\nhh5h\n");
+        let err = read(&data, None).expect_err("Below '@'");
+        assert!(matches!(
+            err.kind,
+            FastqErrorKind::QualityOutOfRange { column: 3, .. }
+        ));
+        assert_eq!(err.record, 10_001);
+
+        // Statistics see Phred+33, the summary has the encoding
+        let mut runner = WorkflowRunner {
+            statistics: vec![Box::new(PhredPerPos::new())],
+        };
+        let summary = runner.process_parallel(&phred64[..], 2).unwrap();
+        assert_eq!(summary.encoding, Some(QualityEncoding::Phred64));
+        let stats = runner.finalize();
+        let phred = downcast::<PhredPerPos>(stats[0].as_ref());
+        assert_eq!(phred.mean(0), Some(30.0));
+    }
+
+    #[test]
+    fn test_probability_mean_quality() {
+        // Q40 and Q2: the error probabilities 0.0001 and 0.631 average to Q5
+        let qual = b"I#";
+        assert_eq!(avg_qual(qual), Some(21.0));
+        let prob = avg_qual_prob(qual).unwrap();
+        assert!((prob - 5.0).abs() < 0.01, "got {}", prob);
+        let errors = expected_errors(qual).unwrap();
+        assert!((errors - 0.631057).abs() < 1e-6, "got {}", errors);
+        assert_eq!(QualityMean::Probability.mean(&[]), None);
+        assert_eq!(
+            QualityMean::Probability
+                .mean(&[30.0, 30.0])
+                .map(|q| q.round()),
+            Some(30.0)
+        );
+
+        let data = b"@r1\nAC\n+\nI#\n@r2\nAC\n+\nII\n";
+        let mut runner = WorkflowRunner {
+            statistics: vec![
+                Box::new(PhredPerPos::new().with_mean(QualityMean::Probability)),
+                Box::new(PhredPerRead::new().with_mean(QualityMean::Probability)),
+            ],
+        };
+        runner.process(&data[..]).unwrap();
+        let stats = runner.finalize();
+        let per_pos = downcast::<PhredPerPos>(stats[0].as_ref());
+        assert_eq!(per_pos.mean(0), Some(40.0));
+        assert!((per_pos.mean(1).unwrap() - 5.0).abs() < 0.01);
+        assert!((per_pos.expected_errors(1) - 0.631057).abs() < 1e-6);
+
+        let reports: Vec<Report> = stats.iter().map(|s| s.report()).collect();
+        assert_eq!(
+            reports[0].tables[0].columns[1],
+            "Average Quality (Error Prob.)"
+        );
+        let metric = |report: usize, name: &str| match reports[report]
+            .metrics
+            .iter()
+            .find(|m| m.name == name)
+            .map(|m| m.value.clone())
+        {
+            Some(Value::Float(value)) => value,
+            other => panic!("{}: {:?}", name, other),
+        };
+        assert!((metric(0, "Expected errors") - 0.631257).abs() < 1e-6);
+        assert!((metric(1, "Min quality (error prob.)") - 5.0).abs() < 0.01);
+        assert!((metric(1, "Mean expected errors") - 0.315629).abs() < 1e-5);
+        assert!((metric(1, "Max expected errors") - 0.631057).abs() < 1e-6);
+
+        // The same results when the reads are split over threads
+        let data = mixed_records(10_000);
+        let new = || WorkflowRunner {
+            statistics: vec![
+                Box::new(PhredPerPos::new().with_mean(QualityMean::Probability)),
+                Box::new(PhredPerRead::new().with_mean(QualityMean::Probability)),
+            ],
+        };
+        let (mut single, mut parallel) = (new(), new());
+        single.process(data.as_slice()).unwrap();
+        parallel.process_parallel(data.as_slice(), 4).unwrap();
+        for (a, b) in single.finalize().iter().zip(parallel.finalize().iter()) {
+            assert_eq!(a.report(), b.report());
+        }
+    }
+}
@@ -1,9 +1,13 @@
-use flate2::read::GzDecoder;
-use std::fs::File;
 use std::io;
-use std::io::BufReader;
+use std::io::BufRead;
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::FastqScanError;
+use crate::input::{is_stdin, open};
 
 // Check if a file exists
 pub fn file_exists(file_path: &Path) -> io::Result<()> {
@@ -22,14 +26,16 @@ pub fn split_data<'a>(data: &'a str, pattern: &str) -> Result<Vec<&'a str>, rege
     Ok(re.split(data).collect())
 }
 
-pub fn process_fastq(file_path: PathBuf) -> BufReader<GzDecoder<File>> {
-    // open and use file
-    if !file_path.exists() {
-        panic!("File not found: {:?}", file_path);
+pub fn process_fastq(file_path: PathBuf) -> Result<Box<dyn BufRead + Send>, FastqScanError> {
+    // open and use file, `-` reads from stdin
+    // Entpacke je nach erkanntem Format
+    match open(&file_path) {
+        Ok((_, reader)) => Ok(reader),
+        Err(source) => Err(FastqScanError::Open {
+            path: file_path,
+            source,
+        }),
     }
-    let file_zip = File::open(file_path).expect("Konnte die Datei nicht öffnen");
-    let file = GzDecoder::new(file_zip); // Entpacke Gzip
-    BufReader::new(file)
 }
 
 pub fn ask_for_len(message: &str) -> Result<u32, String> {
@@ -64,6 +70,64 @@ pub fn avg_qual(qual_str: &[u8]) -> Option<f32> {
     Some(qu_sum / qual_str.len() as f32)
 }
 
+/// Phred score of the mean error probability of the bases, which unlike
+/// [`avg_qual`] is not pulled up by a few high quality bases.
+pub fn avg_qual_prob(qual_str: &[u8]) -> Option<f32> {
+    let phreds = qual_str
+        .iter()
+        .map(|&qu| calculate_phred(qu))
+        .collect::<Option<Vec<f32>>>()?;
+    QualityMean::Probability
+        .mean(&phreds)
+        .map(|mean| mean as f32)
+}
+
+/// Expected number of wrong base calls, the sum of the error probabilities.
+pub fn expected_errors(qual_str: &[u8]) -> Option<f64> {
+    qual_str
+        .iter()
+        .map(|&qu| calculate_phred(qu).map(error_probability))
+        .sum()
+}
+
+/// Probability that a base of Phred score `phred` is wrong.
+pub fn error_probability(phred: f32) -> f64 {
+    10f64.powf(-phred as f64 / 10.0)
+}
+
+/// Phred score of the error probability `p`.
+pub fn phred_from_probability(p: f64) -> f64 {
+    -10.0 * p.log10()
+}
+
+/// How the Phred scores of several bases are averaged.
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QualityMean {
+    /// Mean of the Phred scores.
+    #[default]
+    Arithmetic,
+    /// Phred score of the mean error probability, as reported by ONT and PacBio tools.
+    Probability,
+}
+
+impl QualityMean {
+    /// The mean of `phreds`, `None` if it is empty.
+    pub fn mean(self, phreds: &[f32]) -> Option<f64> {
+        if phreds.is_empty() {
+            return None;
+        }
+        let n = phreds.len() as f64;
+        Some(match self {
+            QualityMean::Arithmetic => phreds.iter().map(|&q| q as f64).sum::<f64>() / n,
+            QualityMean::Probability => phred_from_probability(
+                phreds.iter().map(|&q| error_probability(q)).sum::<f64>() / n,
+            ),
+        })
+    }
+}
+
+/// Phred score of a Phred+33 quality character. Records from `FastqReader`
+/// are converted to Phred+33 whatever the encoding of the input.
 pub fn calculate_phred(qual: u8) -> Option<f32> {
     if (33..=126).contains(&qual) {
         Some((qual as f32) - 33.0)
@@ -72,164 +136,131 @@ pub fn calculate_phred(qual: u8) -> Option<f32> {
     }
 }
 
-// --------------------------- READ/FILE INFO -------------------------
-const DATA_PATTERN: &str = r"[_\.]";
-const READ_PATTERN: &str = r"[ :\.]";
-
-#[derive(Debug)]
-struct DataInfo {
-    sample_name: String,
-    barcode_sequence: String,
-    lane_number: usize,
-    read_number: usize,
-    set_number: usize,
+// ------------------- SEQUENCES -------------------
+/// Reverse complement of a nucleotide sequence, unknown bases become `N`.
+pub fn reverse_complement(seq: &[u8]) -> Vec<u8> {
+    seq.iter()
+        .rev()
+        .map(|&base| match base.to_ascii_uppercase() {
+            b'A' => b'T',
+            b'C' => b'G',
+            b'G' => b'C',
+            b'T' => b'A',
+            _ => b'N',
+        })
+        .collect()
 }
 
-impl DataInfo {
-    // Constructor:
-    pub fn new(info: Vec<&str>) -> Result<Self, String> {
-        if info.len() < 5 {
-            return Err("Data is missing Information".to_string());
+/// Read the entries of a FASTA file (plain or compressed) as (name, sequence) pairs.
+///
+/// The name is the whole header line without `>`, sequences spanning several
+/// lines are joined and converted to upper case.
+pub fn read_fasta(path: &Path) -> io::Result<Vec<(String, Vec<u8>)>> {
+    let (_, reader) = open(path)?;
+    let mut entries: Vec<(String, Vec<u8>)> = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if let Some(name) = line.strip_prefix('>') {
+            entries.push((name.trim().to_string(), Vec::new()));
+        } else if !line.is_empty() {
+            let Some((_, seq)) = entries.last_mut() else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "FASTA sequence without a header line",
+                ));
+            };
+            seq.extend(line.bytes().map(|b| b.to_ascii_uppercase()));
         }
-
-        // check for conversion errors
-        let lane_number = info[2][1..]
-            .parse::<usize>()
-            .map_err(|_| "Failed to parse lane_number")?;
-        let read_number = info[3][1..]
-            .parse::<usize>()
-            .map_err(|_| "Failed to parse read_number")?;
-        let set_number = info[4]
-            .parse::<usize>()
-            .map_err(|_| "Failed to parse set_number")?;
-
-        // put info into new struct
-        Ok(DataInfo {
-            sample_name: info[0].to_string(),
-            barcode_sequence: info[1].to_string(),
-            lane_number,
-            read_number,
-            set_number,
-        })
-    }
-
-    pub fn display(&self) {
-        println!("\nAn overview of your file!");
-        println!("Name of the sample: {}", self.sample_name);
-        println!("Barcode sequence: {}", self.barcode_sequence);
-        println!("Lane number (1 -8): {}", self.lane_number);
-        println!("Read number (1 or 2): {}", self.read_number);
-        println!("Set number: {}", self.set_number);
     }
+    Ok(entries)
 }
 
-#[derive(Debug)]
-struct ReadInfo {
-    instrument: String,
-    run: usize,
-    flowcell_id: String,
-    lane: usize,
-    tile_number: usize,
-    x_pos: usize,
-    y_pos: usize,
-    read: usize,
-    is_filtered: char,
-    control_number: usize,
-    index: String,
-}
-
-impl ReadInfo {
-    // Constructor:
-    pub fn new(info: Vec<&str>) -> Result<Self, String> {
-        if info.len() < 11 {
-            return Err("Data is missing Information".to_string());
-        }
+// ----------------------------- FILE INFO ----------------------------
+// Illumina bcl2fastq / BCL Convert file names, e.g. `Sample_S1_L001_R1_001`,
+// or `Sample_ACGTACGT_L001_R1_001` with the barcode instead of the sample number.
+static ILLUMINA_NAME: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(
+        r"^(?P<sample>.+?)_(?:S(?P<number>\d+)|(?P<barcode>[ACGTN]+(?:[-+][ACGTN]+)?))(?:_L(?P<lane>\d{3}))?_(?P<kind>[RI])(?P<read>\d)_(?P<set>\d{3})$",
+    )
+    .expect("Invalid Illumina file name pattern")
+});
 
-        // Check for conversion errors
-        let run = info[1]
-            .parse::<usize>()
-            .map_err(|_| "Failed to parse lane_number")?;
-        let lane = info[3]
-            .parse::<usize>()
-            .map_err(|_| "Failed to parse read_number")?;
-        let tile_number = info[4]
-            .parse::<usize>()
-            .map_err(|_| "Failed to parse set_number")?;
-        let x_pos = info[5]
-            .parse::<usize>()
-            .map_err(|_| "Failed to parse read_number")?;
-        let y_pos = info[6]
-            .parse::<usize>()
-            .map_err(|_| "Failed to parse read_number")?;
-        let read = info[7]
-            .parse::<usize>()
-            .map_err(|_| "Failed to parse read_number")?;
-        let is_filtered = info[8]
-            .parse::<char>()
-            .map_err(|_| "Failed to parse read_number")?;
-        let control_number = info[9]
-            .parse::<usize>()
-            .map_err(|_| "Failed to parse read_number")?;
-
-        // put info into new struct
-        Ok(ReadInfo {
-            instrument: info[0].to_string(),
-            run,
-            flowcell_id: info[2].to_string(),
-            lane,
-            tile_number,
-            x_pos,
-            y_pos,
-            read,
-            is_filtered,
-            control_number,
-            index: info[10].to_string(),
-        })
-    }
+// Other names ending in the read number, e.g. `SRR123_1` or `Sample.R2`.
+static READ_NAME: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(r"^(?P<sample>.+?)[_.]R?(?P<read>[12])$")
+        .expect("Invalid read file name pattern")
+});
 
-    pub fn display(&self) {
-        println!("\nHier die übersicht über deine read Datei!");
-        println!(
-            "Die eindeutige Gerätebezeichnung: {}",
-            &self.instrument[1..]
-        ); // Remove leading character
-        println!("Die Lauf-ID : {}", self.run);
-        println!("Flowcell-ID: {}", self.flowcell_id);
-        println!("Flowcell-Lane (Spur: 1–8): {}", self.lane);
-        println!("Tile-Nummer: {}", self.tile_number);
-        println!("X-Koordinate: {}", self.x_pos);
-        println!("Y-Koordinate: {}", self.y_pos);
-        println!("Mitglied eines Paares (1 oder 2): {}", self.read);
-        println!("Chastity Filter information: {}", self.is_filtered);
-        println!("Kontrollbits: {}", self.control_number);
-        println!("Indexsequenz: {}", self.index);
-    }
+// Extensions removed from the file name before parsing it.
+const COMPRESSION_EXTENSIONS: [&str; 5] = [".gz", ".bgz", ".bz2", ".xz", ".zst"];
+const FASTQ_EXTENSIONS: [&str; 2] = [".fastq", ".fq"];
+
+/// Sample information from the name of an input file.
+///
+/// Illumina names like `Sample_S1_L001_R1_001.fastq.gz` fill in all fields.
+/// Other names give the sample name without extensions and, if the name ends
+/// in `_1`/`_2` or `_R1`/`_R2`, the read number.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SampleMetadata {
+    /// The file name without directories, `-` for stdin.
+    pub file_name: String,
+    pub sample_name: String,
+    pub sample_number: Option<usize>,
+    pub barcode: Option<String>,
+    pub lane: Option<usize>,
+    /// Read number, 1 or 2 (or the index read number for `I1`/`I2` files).
+    pub read: Option<usize>,
+    pub index_read: bool,
+    pub set_number: Option<usize>,
 }
 
-pub fn info_data(data: &str) {
-    // First we split the File name into its parts
-    match split_data(data, DATA_PATTERN) {
-        Ok(info) => {
-            // We create a new struct and display it
-            match DataInfo::new(info) {
-                Ok(datainfo) => datainfo.display(),
-                Err(e) => println!("Could not display your data: {e}"),
-            }
+impl SampleMetadata {
+    pub fn from_path(path: &Path) -> Self {
+        if is_stdin(path) {
+            return SampleMetadata {
+                file_name: "-".to_string(),
+                sample_name: "stdin".to_string(),
+                ..Default::default()
+            };
         }
-        Err(e) => println!("Couldnt split input name of your data: {e}"),
-    }
-}
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.to_string_lossy().into_owned());
 
-pub fn info_read(data: &str) {
-    // First we split the File name into its parts
-    match split_data(data, READ_PATTERN) {
-        Ok(info) => {
-            match ReadInfo::new(info) {
-                //We create a new struct and display it
-                Ok(read_info) => read_info.display(),
-                Err(e) => println!("Couldnt display your read data: {e}"),
+        let mut stem = file_name.as_str();
+        for extensions in [&COMPRESSION_EXTENSIONS[..], &FASTQ_EXTENSIONS[..]] {
+            if let Some(ext) = extensions.iter().find(|ext| stem.ends_with(*ext)) {
+                stem = &stem[..stem.len() - ext.len()];
             }
         }
-        Err(e) => println!("Couldnt split input name of your read: {e}"),
+
+        let number = |captures: &regex::Captures, name: &str| {
+            captures.name(name).and_then(|m| m.as_str().parse().ok())
+        };
+        let mut metadata = SampleMetadata {
+            sample_name: stem.to_string(),
+            ..Default::default()
+        };
+        if let Some(captures) = ILLUMINA_NAME.captures(stem) {
+            metadata = SampleMetadata {
+                sample_name: captures["sample"].to_string(),
+                sample_number: number(&captures, "number"),
+                barcode: captures.name("barcode").map(|m| m.as_str().to_string()),
+                lane: number(&captures, "lane"),
+                read: number(&captures, "read"),
+                index_read: &captures["kind"] == "I",
+                set_number: number(&captures, "set"),
+                ..metadata
+            };
+        } else if let Some(captures) = READ_NAME.captures(stem) {
+            metadata.sample_name = captures["sample"].to_string();
+            metadata.read = number(&captures, "read");
+        }
+        SampleMetadata {
+            file_name,
+            ..metadata
+        }
     }
 }